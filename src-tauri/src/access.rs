@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::keys::{self, KeyLabels};
+use crate::nix_eval::ResolvedAccess;
+use crate::nix_parser::{self, GroupDef};
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatrixFormat {
+    Csv,
+    Markdown,
+}
+
+/// A recipient key and what we know about who holds it.
+#[derive(Debug, Serialize, Clone)]
+pub struct Principal {
+    /// The key without its comment.
+    pub key: String,
+    pub label: String,
+    /// Group bindings that include this key.
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AccessRow {
+    pub path: String,
+    /// One entry per principal, in `AccessMatrix::principals` order.
+    pub readers: Vec<bool>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AccessMatrix {
+    pub principals: Vec<Principal>,
+    pub secrets: Vec<AccessRow>,
+}
+
+/// Build the secrets × principals matrix from evaluated declarations.
+pub fn build(resolved: &ResolvedAccess, labels: &KeyLabels) -> AccessMatrix {
+    // Deduplicate by key without comment, preferring a form with a comment
+    let mut full_keys: BTreeMap<String, &String> = BTreeMap::new();
+    for key in resolved.secrets.values().chain(resolved.groups.values()).flatten() {
+        let seen = full_keys.entry(keys::normalize_key(key)).or_insert(key);
+        if keys::key_comment(seen).is_none() {
+            *seen = key;
+        }
+    }
+    let mut by_key: BTreeMap<String, Principal> = full_keys.into_iter()
+        .map(|(key, full)| (key.clone(), Principal {
+            key,
+            label: keys::label_for(full, labels),
+            groups: Vec::new(),
+        }))
+        .collect();
+    let mut group_names: Vec<&String> = resolved.groups.keys().collect();
+    group_names.sort();
+    for group in group_names {
+        for key in &resolved.groups[group] {
+            if let Some(p) = by_key.get_mut(&keys::normalize_key(key)) {
+                if !p.groups.contains(group) {
+                    p.groups.push(group.clone());
+                }
+            }
+        }
+    }
+
+    let mut principals: Vec<Principal> = by_key.into_values().collect();
+    principals.sort_by(|a, b| a.label.to_lowercase().cmp(&b.label.to_lowercase()).then_with(|| a.key.cmp(&b.key)));
+
+    let mut paths: Vec<&String> = resolved.secrets.keys().collect();
+    paths.sort();
+    let secrets = paths.into_iter().map(|path| {
+        let readers: Vec<String> = resolved.secrets[path].iter().map(|k| keys::normalize_key(k)).collect();
+        AccessRow {
+            path: path.clone(),
+            readers: principals.iter().map(|p| readers.contains(&p.key)).collect(),
+        }
+    }).collect();
+
+    AccessMatrix { principals, secrets }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct LabelledKey {
+    pub key: String,
+    pub label: String,
+}
+
+/// One name a group definition refers to, with the keys behind it.
+#[derive(Debug, Serialize, Clone)]
+pub struct GroupMember {
+    pub reference: String,
+    pub keys: Vec<LabelledKey>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ExpandedGroup {
+    pub name: String,
+    pub definition: String,
+    /// Every key the group resolves to, without duplicates.
+    pub keys: Vec<LabelledKey>,
+    /// The bindings and `meta` paths the definition is built from. Names
+    /// that are neither, such as `builtins` functions, are left out.
+    pub members: Vec<GroupMember>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct GroupList {
+    pub groups: Vec<ExpandedGroup>,
+    pub warnings: Vec<String>,
+}
+
+fn labelled(keys: &[String], labels: &KeyLabels) -> Vec<LabelledKey> {
+    let mut out: Vec<LabelledKey> = Vec::new();
+    for full in keys {
+        let key = keys::normalize_key(full);
+        if !out.iter().any(|l| l.key == key) {
+            out.push(LabelledKey { label: keys::label_for(full, labels), key });
+        }
+    }
+    out
+}
+
+/// Attach evaluated keys to each group definition, along with the keys of
+/// every binding or attribute path it refers to.
+pub fn expand_groups(groups: &[GroupDef], resolved: &ResolvedAccess, labels: &KeyLabels) -> Vec<ExpandedGroup> {
+    groups.iter()
+        .map(|group| {
+            let members = nix_parser::references(&group.definition).into_iter()
+                .filter_map(|reference| {
+                    let keys = resolved.groups.get(&reference)
+                        .or_else(|| resolved.references.get(&reference))
+                        .or_else(|| resolved.args.get(&reference));
+                    let known = groups.iter().any(|g| g.name == reference) || keys.is_some();
+                    known.then(|| GroupMember {
+                        keys: labelled(keys.map(Vec::as_slice).unwrap_or_default(), labels),
+                        reference,
+                    })
+                })
+                .collect();
+            ExpandedGroup {
+                name: group.name.clone(),
+                definition: group.definition.clone(),
+                keys: labelled(resolved.groups.get(&group.name).map(Vec::as_slice).unwrap_or_default(), labels),
+                members,
+            }
+        })
+        .collect()
+}
+
+/// How a change alters who can read one secret.
+#[derive(Debug, Serialize, Clone)]
+pub struct AccessChange {
+    pub path: String,
+    pub gained: Vec<LabelledKey>,
+    pub lost: Vec<LabelledKey>,
+}
+
+/// Compare two evaluations of the declarations, secret by secret. Secrets
+/// whose recipients do not change are left out.
+pub fn diff(old: &ResolvedAccess, new: &ResolvedAccess, labels: &KeyLabels) -> Vec<AccessChange> {
+    let normalized = |keys: Option<&Vec<String>>| -> Vec<(String, String)> {
+        keys.into_iter().flatten()
+            .map(|k| (keys::normalize_key(k), keys::label_for(k, labels)))
+            .collect()
+    };
+    let missing_from = |from: &[(String, String)], other: &[(String, String)]| -> Vec<LabelledKey> {
+        let mut out: Vec<LabelledKey> = Vec::new();
+        for (key, label) in from {
+            if !other.iter().any(|(k, _)| k == key) && !out.iter().any(|l| l.key == *key) {
+                out.push(LabelledKey { key: key.clone(), label: label.clone() });
+            }
+        }
+        out
+    };
+
+    let mut paths: Vec<&String> = old.secrets.keys().chain(new.secrets.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    paths.into_iter()
+        .filter_map(|path| {
+            let before = normalized(old.secrets.get(path));
+            let after = normalized(new.secrets.get(path));
+            let change = AccessChange {
+                path: path.clone(),
+                gained: missing_from(&after, &before),
+                lost: missing_from(&before, &after),
+            };
+            (!change.gained.is_empty() || !change.lost.is_empty()).then_some(change)
+        })
+        .collect()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+pub fn render(matrix: &AccessMatrix, format: MatrixFormat) -> String {
+    match format {
+        MatrixFormat::Csv => to_csv(matrix),
+        MatrixFormat::Markdown => to_markdown(matrix),
+    }
+}
+
+/// One row per secret, one column per principal; `x` marks read access.
+pub fn to_csv(matrix: &AccessMatrix) -> String {
+    let mut out = String::from("secret");
+    for p in &matrix.principals {
+        out.push(',');
+        out.push_str(&csv_field(&p.label));
+    }
+    out.push('\n');
+    for row in &matrix.secrets {
+        out.push_str(&csv_field(&row.path));
+        for &read in &row.readers {
+            out.push_str(if read { ",x" } else { "," });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// A Markdown table followed by a legend mapping labels to keys.
+pub fn to_markdown(matrix: &AccessMatrix) -> String {
+    let mut out = String::from("| Secret |");
+    for p in &matrix.principals {
+        out.push_str(&format!(" {} |", markdown_cell(&p.label)));
+    }
+    out.push_str("\n|---|");
+    out.push_str(&"---|".repeat(matrix.principals.len()));
+    out.push('\n');
+    for row in &matrix.secrets {
+        out.push_str(&format!("| `{}` |", markdown_cell(&row.path)));
+        for &read in &row.readers {
+            out.push_str(if read { " ✓ |" } else { "  |" });
+        }
+        out.push('\n');
+    }
+
+    out.push_str("\n| Principal | Key | Groups |\n|---|---|---|\n");
+    for p in &matrix.principals {
+        out.push_str(&format!(
+            "| {} | `{}` | {} |\n",
+            markdown_cell(&p.label),
+            markdown_cell(&p.key),
+            markdown_cell(&p.groups.join(", "))
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_and_render() {
+        let alice = "ssh-ed25519 AAAAalice alice@laptop";
+        let ci = "ssh-ed25519 AAAAci";
+        let mut resolved = ResolvedAccess::default();
+        resolved.secrets.insert("b.age".to_string(), vec![alice.to_string(), ci.to_string()]);
+        resolved.secrets.insert("a.age".to_string(), vec!["ssh-ed25519 AAAAalice".to_string()]);
+        resolved.groups.insert("tech".to_string(), vec![alice.to_string()]);
+
+        let mut labels = KeyLabels::new();
+        labels.insert("ssh-ed25519 AAAAci".to_string(), "CI, runner".to_string());
+        let matrix = build(&resolved, &labels);
+
+        let labels: Vec<&str> = matrix.principals.iter().map(|p| p.label.as_str()).collect();
+        assert_eq!(labels, vec!["alice@laptop", "CI, runner"]);
+        assert_eq!(matrix.principals[0].groups, vec!["tech"]);
+        assert_eq!(matrix.secrets[0].path, "a.age");
+        assert_eq!(matrix.secrets[0].readers, vec![true, false]);
+
+        assert_eq!(to_csv(&matrix), "secret,alice@laptop,\"CI, runner\"\na.age,x,\nb.age,x,x\n");
+        let md = to_markdown(&matrix);
+        assert!(md.starts_with("| Secret | alice@laptop | CI, runner |\n|---|---|---|\n| `a.age` | ✓ |  |\n"));
+        assert!(md.contains("| alice@laptop | `ssh-ed25519 AAAAalice` | tech |"));
+    }
+
+    #[test]
+    fn test_expand_groups() {
+        let groups = vec![
+            GroupDef { name: "admins".to_string(), definition: "[ alice ]".to_string() },
+            GroupDef { name: "alice".to_string(), definition: "\"ssh-ed25519 AAAAalice alice\"".to_string() },
+            GroupDef { name: "all".to_string(), definition: "admins ++ meta.ssh.groups.TECH ++ builtins.attrValues meta.ssh.nodes".to_string() },
+        ];
+        let mut resolved = ResolvedAccess::default();
+        resolved.groups.insert("admins".to_string(), vec!["ssh-ed25519 AAAAalice alice".to_string()]);
+        resolved.groups.insert("alice".to_string(), vec!["ssh-ed25519 AAAAalice alice".to_string()]);
+        resolved.groups.insert("all".to_string(), vec![
+            "ssh-ed25519 AAAAalice alice".to_string(),
+            "ssh-ed25519 AAAAbob bob".to_string(),
+            "ssh-ed25519 AAAAalice".to_string(),
+        ]);
+        resolved.references.insert("meta.ssh.groups.TECH".to_string(), vec!["ssh-ed25519 AAAAbob bob".to_string()]);
+
+        let expanded = expand_groups(&groups, &resolved, &KeyLabels::new());
+        assert_eq!(expanded[0].members[0].reference, "alice");
+        let all = &expanded[2];
+        assert_eq!(all.keys.len(), 2);
+        let members: Vec<&str> = all.members.iter().map(|m| m.reference.as_str()).collect();
+        assert_eq!(members, vec!["admins", "meta.ssh.groups.TECH"]);
+        assert_eq!(all.members[1].keys[0].label, "bob");
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut old = ResolvedAccess::default();
+        old.secrets.insert("a.age".to_string(), vec!["ssh-ed25519 AAAAalice alice".to_string()]);
+        old.secrets.insert("b.age".to_string(), vec!["ssh-ed25519 AAAAalice alice".to_string()]);
+        let mut new = ResolvedAccess::default();
+        new.secrets.insert("a.age".to_string(), vec!["ssh-ed25519 AAAAbob bob".to_string()]);
+        new.secrets.insert("b.age".to_string(), vec!["ssh-ed25519 AAAAalice".to_string()]);
+
+        let changes = diff(&old, &new, &KeyLabels::new());
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "a.age");
+        assert_eq!(changes[0].gained[0].label, "bob");
+        assert_eq!(changes[0].lost, vec![LabelledKey {
+            key: "ssh-ed25519 AAAAalice".to_string(),
+            label: "alice".to_string(),
+        }]);
+    }
+}
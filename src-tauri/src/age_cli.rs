@@ -1,24 +1,25 @@
 use std::path::Path;
 use std::process::Command;
 use std::sync::Mutex;
+use crate::process;
 
 static AGE_BINARY: Mutex<Option<String>> = Mutex::new(None);
 
 pub fn find_age_binary() -> Result<String, String> {
     // Try `which age` first
-    let output = Command::new("which")
-        .arg("age")
-        .output()
-        .map_err(|e| format!("Failed to run 'which age': {}", e))?;
+    let timeout = process::timeouts().age();
+    let output = process::run(Command::new("which").arg("age"), None, timeout)?;
 
     if output.status.success() {
         return Ok(String::from_utf8_lossy(&output.stdout).trim().to_string());
     }
 
     // Search nix store
-    let nix_output = Command::new("bash")
-        .args(["-c", "ls /nix/store/*/bin/age 2>/dev/null | head -1"])
-        .output();
+    let nix_output = process::run(
+        Command::new("bash").args(["-c", "ls /nix/store/*/bin/age 2>/dev/null | head -1"]),
+        None,
+        timeout,
+    );
 
     if let Ok(nix_output) = nix_output {
         let path = String::from_utf8_lossy(&nix_output.stdout).trim().to_string();
@@ -42,12 +43,11 @@ fn age_binary() -> Result<String, String> {
 
 pub fn decrypt_file(file_path: &Path, identity_path: &Path) -> Result<String, String> {
     let age = age_binary()?;
-    let output = Command::new(&age)
-        .args(["-d", "-i"])
+    let mut cmd = Command::new(&age);
+    cmd.args(["-d", "-i"])
         .arg(identity_path)
-        .arg(file_path)
-        .output()
-        .map_err(|e| format!("Failed to run age: {}", e))?;
+        .arg(file_path);
+    let output = process::run(&mut cmd, None, process::timeouts().age())?;
 
     if output.status.success() {
         Ok(String::from_utf8_lossy(&output.stdout).to_string())
@@ -61,34 +61,20 @@ pub fn encrypt_to_file(
     plaintext: &str,
     output_path: &Path,
     recipient_keys: &[String],
+    armor: bool,
 ) -> Result<(), String> {
-    use std::io::Write;
-    use std::process::Stdio;
-
     let age = age_binary()?;
     let mut cmd = Command::new(&age);
     cmd.arg("-e");
+    if armor {
+        cmd.arg("-a");
+    }
     for key in recipient_keys {
         cmd.args(["-r", key]);
     }
     cmd.arg("-o").arg(output_path);
 
-    let mut child = cmd
-        .stdin(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to spawn age: {}", e))?;
-
-    // Take stdin and drop it after writing to send EOF
-    {
-        let mut stdin = child.stdin.take()
-            .ok_or("Failed to open stdin")?;
-        stdin.write_all(plaintext.as_bytes())
-            .map_err(|e| format!("Failed to write to age stdin: {}", e))?;
-    }
-
-    let output = child.wait_with_output()
-        .map_err(|e| format!("Failed to wait for age: {}", e))?;
+    let output = process::run(&mut cmd, Some(plaintext.as_bytes()), process::timeouts().age())?;
 
     if output.status.success() {
         Ok(())
@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+use crate::commands::rotation::DEFAULT_SOON_DAYS;
+use crate::layout;
+use crate::rotation::{self, RotationStatus};
+
+const USAGE: &str = "Usage: thoughtseize rotation-report <project-dir> [--interval-days N] [--soon-days N] [--json]";
+
+/// Handle command-line subcommands. Returns the exit code when one ran, or
+/// `None` to start the GUI.
+pub fn run(args: &[String]) -> Option<i32> {
+    match args.first().map(|s| s.as_str()) {
+        Some("rotation-report") => Some(report_exit_code(rotation_report(&args[1..]))),
+        Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Some(0)
+        }
+        _ => None,
+    }
+}
+
+fn report_exit_code(result: Result<bool, String>) -> i32 {
+    match result {
+        Ok(true) => 1,
+        Ok(false) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            2
+        }
+    }
+}
+
+fn parse_days(value: Option<&String>, flag: &str) -> Result<u32, String> {
+    value.and_then(|v| v.parse().ok())
+        .ok_or_else(|| format!("{} expects a number of days\n{}", flag, USAGE))
+}
+
+/// Print the rotation report; returns whether anything is overdue.
+fn rotation_report(args: &[String]) -> Result<bool, String> {
+    let mut dir = None;
+    let mut interval = None;
+    let mut soon = DEFAULT_SOON_DAYS;
+    let mut json = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--interval-days" => interval = Some(parse_days(iter.next(), arg)?),
+            "--soon-days" => soon = parse_days(iter.next(), arg)?,
+            "--json" => json = true,
+            _ if dir.is_none() && !arg.starts_with('-') => dir = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument: {}\n{}", arg, USAGE)),
+        }
+    }
+    let dir = dir.ok_or_else(|| USAGE.to_string())?;
+
+    let project_dir = PathBuf::from(&dir).canonicalize()
+        .map_err(|e| format!("Cannot open {}: {}", dir, e))?;
+    let overrides = layout::load_overrides(&project_dir.to_string_lossy());
+    // The top-level root, without the files of roots nested in it
+    let layout = layout::discover(&project_dir, &overrides)?.remove(0);
+    let report = rotation::report(&layout, interval, soon)?;
+
+    if json {
+        let out = serde_json::to_string_pretty(&report)
+            .map_err(|e| format!("Failed to serialize report: {}", e))?;
+        println!("{}", out);
+    } else {
+        for warning in &report.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        print_section("Overdue", &report.overdue);
+        print_section("Due soon", &report.due_soon);
+        if !report.unscheduled.is_empty() {
+            println!("No rotation schedule: {}", report.unscheduled.len());
+        }
+    }
+    Ok(!report.overdue.is_empty())
+}
+
+fn print_section(title: &str, secrets: &[RotationStatus]) {
+    println!("{} ({}):", title, secrets.len());
+    for s in secrets {
+        println!(
+            "  {}  due {}  ({} days, last changed {})",
+            s.path,
+            s.due.format("%Y-%m-%d"),
+            s.days_left,
+            s.last_changed.format("%Y-%m-%d"),
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::fs;
+use tauri::{AppHandle, State};
+use crate::state::AppState;
+use crate::access::{self, AccessMatrix, MatrixFormat};
+use crate::keys::{self, KeyLabels};
+use crate::nix_eval;
+use super::tasks::run_task;
+
+fn compute_matrix(state: &AppState) -> Result<AccessMatrix, String> {
+    let layout = state.layout()?;
+    let resolved = nix_eval::evaluate_declarations(&layout, &layout.read_declarations()?)?;
+    let labels = keys::load_labels(&layout)?;
+    Ok(access::build(&resolved, &labels))
+}
+
+/// Who can read what: every secret against every concrete recipient key.
+#[tauri::command]
+pub async fn access_matrix(task_id: Option<String>, app: AppHandle) -> Result<AccessMatrix, String> {
+    run_task(app, task_id, compute_matrix).await
+}
+
+/// Write the access matrix as CSV or Markdown for an access review.
+#[tauri::command]
+pub async fn export_access_matrix(
+    format: MatrixFormat,
+    output_path: String,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<String, String> {
+    run_task(app, task_id, move |state| {
+        let matrix = compute_matrix(state)?;
+        fs::write(&output_path, access::render(&matrix, format))
+            .map_err(|e| format!("Failed to write {}: {}", output_path, e))?;
+        Ok(output_path)
+    }).await
+}
+
+/// Name a recipient key in the project's key label file; an empty or missing
+/// label removes it.
+#[tauri::command]
+pub fn set_key_label(key: String, label: Option<String>, state: State<AppState>) -> Result<KeyLabels, String> {
+    let layout = state.layout()?;
+    let mut labels = keys::load_labels(&layout)?;
+    let key = keys::normalize_key(&key);
+    match label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty()) {
+        Some(label) => labels.insert(key, label),
+        None => labels.remove(&key),
+    };
+    keys::save_labels(&layout, &labels)?;
+    Ok(labels)
+}
@@ -0,0 +1,436 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use crate::age_cli;
+use crate::formats::{self, SecretField, SecretFormat};
+use crate::layout;
+use crate::nix_eval;
+use crate::nix_parser;
+use crate::tasks;
+use crate::plan::{ChangePlan, FileOp, PlanPreview};
+use super::plans;
+use super::secrets::safe_resolve;
+use super::tasks::run_task;
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Dotenv,
+    Json,
+    Shell,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NamingStyle {
+    /// `watch/GEMINI_API_KEY.age` -> `GEMINI_API_KEY`
+    Basename,
+    /// `watch/GEMINI_API_KEY.age` -> `WATCH_GEMINI_API_KEY`
+    FullPath,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportNaming {
+    pub style: NamingStyle,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default = "default_true")]
+    pub uppercase: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(serde::Serialize)]
+pub struct ExportResult {
+    pub path: String,
+    pub count: usize,
+    pub warnings: Vec<String>,
+}
+
+struct ImportItem {
+    name: String,
+    content: String,
+}
+
+/// Collect `(name, plaintext)` pairs from a `.env` file (one per variable) or
+/// from every regular file below a directory (named by relative path),
+/// skipping what `layout::walk` skips.
+fn collect_import_items(source: &Path) -> Result<Vec<ImportItem>, String> {
+    if source.is_file() {
+        let content = fs::read_to_string(source)
+            .map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+        let fields = formats::parse_fields(SecretFormat::Dotenv, &content)?;
+        return Ok(fields.into_iter()
+            .map(|f| ImportItem { name: f.key, content: f.value })
+            .collect());
+    }
+
+    if !source.is_dir() {
+        return Err(format!("Import source not found: {}", source.display()));
+    }
+
+    let mut paths: Vec<PathBuf> = layout::walk(source, |_| true).collect();
+    paths.sort();
+    let mut items = Vec::new();
+    for path in paths {
+        let name = path.strip_prefix(source)
+            .map_err(|_| "Invalid import path".to_string())?
+            .to_string_lossy()
+            .to_string();
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {} (only text files can be imported): {}", name, e))?;
+        items.push(ImportItem { name, content });
+    }
+    Ok(items)
+}
+
+/// Whether `path`, relative to the secret root, lies below `folder`; the
+/// empty folder is the root itself.
+fn in_folder(path: &str, folder: &str) -> bool {
+    folder.is_empty() || path.strip_prefix(folder).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Expand a naming template such as `app/{name}.age`. Supported placeholders
+/// are `{name}` and `{name_lower}`; `.age` is appended when missing.
+fn apply_template(template: &str, name: &str) -> String {
+    let mut path = template
+        .replace("{name_lower}", &name.to_lowercase())
+        .replace("{name}", name);
+    if !path.ends_with(".age") {
+        path.push_str(".age");
+    }
+    path
+}
+
+#[tauri::command]
+pub async fn import_secrets(
+    source: String,
+    target_template: String,
+    groups: Vec<String>,
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<PlanPreview, String> {
+    run_task(app, task_id, move |state| {
+        for g in &groups {
+            if !nix_parser::is_key_reference(g) {
+                return Err(format!("Invalid group: '{}'. Use a binding name or an attribute path like meta.ssh.groups.TECH.", g));
+            }
+        }
+        if groups.is_empty() {
+            return Err("At least one group is required".to_string());
+        }
+        if !target_template.contains("{name}") && !target_template.contains("{name_lower}") {
+            return Err("Target template must contain {name} or {name_lower}".to_string());
+        }
+
+        let layout = state.layout()?;
+        let items = collect_import_items(Path::new(&source))?;
+        if items.is_empty() {
+            return Err("Nothing to import".to_string());
+        }
+
+        let mut plan = ChangePlan::new(&layout)?;
+        let parsed = nix_parser::parse_meta_secrets(plan.base())?;
+
+        // Validate every target before touching anything
+        let mut targets: Vec<(String, PathBuf, String)> = Vec::new();
+        for item in items {
+            let relative_path = apply_template(&target_template, &item.name);
+            // Names come from the source, and paths are quoted into the declarations
+            nix_eval::validate_secret_path(&relative_path)
+                .map_err(|e| format!("{}: {}", relative_path, e))?;
+            let file_path = safe_resolve(&layout.secret_root, &relative_path)?;
+            if file_path.exists() || parsed.secrets.iter().any(|s| s.path == relative_path) {
+                return Err(format!("Secret already exists: {}", relative_path));
+            }
+            if targets.iter().any(|(p, _, _)| *p == relative_path) {
+                return Err(format!("Template maps several sources to {}", relative_path));
+            }
+            targets.push((relative_path, file_path, item.content));
+        }
+
+        // All entries go into the declarations file in one edit; the plan rolls
+        // back both the edit and any encrypted files if a later step fails
+        let paths: Vec<&str> = targets.iter().map(|(p, _, _)| p.as_str()).collect();
+        let group_refs: Vec<&str> = groups.iter().map(|s| s.as_str()).collect();
+        let new_meta = nix_parser::add_secret_entries(plan.base(), &paths, &group_refs, false);
+        plan.set_declarations(new_meta);
+        for (path, file, plaintext) in targets {
+            plan.push(FileOp::Write { path, file, plaintext });
+        }
+        plans::submit(plan, dry_run, state)
+    }).await
+}
+
+/// Derive an environment variable name from a secret path.
+fn variable_name(secret_path: &str, naming: &ExportNaming) -> String {
+    let without_ext = secret_path.trim_end_matches(".age");
+    let source = match naming.style {
+        NamingStyle::Basename => without_ext.rsplit('/').next().unwrap_or(without_ext),
+        NamingStyle::FullPath => without_ext,
+    };
+    let mut name: String = format!("{}{}", naming.prefix, source)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if naming.uppercase {
+        name = name.to_uppercase();
+    }
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Variable names for `paths`, in order. Two paths mapping to one name is an
+/// error rather than one value silently replacing the other.
+fn variable_names(paths: &[String], naming: &ExportNaming) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        let name = variable_name(path, naming);
+        if let Some(existing) = names.iter().position(|n| *n == name) {
+            return Err(format!(
+                "{} and {} both map to {}; use full-path naming or a different selection",
+                paths[existing], paths[i], name
+            ));
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Single-quote `value` for POSIX shells, where nothing inside is expanded.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn render_export(format: ExportFormat, vars: &[SecretField]) -> Result<String, String> {
+    match format {
+        ExportFormat::Dotenv => formats::apply_fields(SecretFormat::Dotenv, "", vars),
+        ExportFormat::Json => {
+            let map: serde_json::Map<String, serde_json::Value> = vars.iter()
+                .map(|v| (v.key.clone(), serde_json::Value::String(v.value.clone())))
+                .collect();
+            serde_json::to_string_pretty(&map)
+                .map(|s| s + "\n")
+                .map_err(|e| format!("Failed to serialize JSON: {}", e))
+        }
+        ExportFormat::Shell => Ok(vars.iter()
+            .map(|v| format!("export {}={}\n", v.key, shell_quote(&v.value)))
+            .collect()),
+    }
+}
+
+/// Return the enclosing git working tree, if any.
+fn git_work_tree(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(|dir| dir.to_path_buf())
+}
+
+/// Write a file readable only by the current user.
+fn write_private(path: &Path, content: &str) -> Result<(), String> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    // The mode above only applies to newly created files
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to set permissions on {}: {}", path.display(), e))?;
+    }
+    file.write_all(content.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Decrypt a set of secrets (explicit paths and/or every secret under a folder)
+/// and write them as a single dotenv, JSON or shell-export bundle.
+#[tauri::command]
+pub async fn export_secrets(
+    paths: Vec<String>,
+    folder: Option<String>,
+    format: ExportFormat,
+    naming: ExportNaming,
+    output_path: String,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<ExportResult, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+        let identity_path = state.identity_path()?;
+
+        let mut selected = paths;
+        if let Some(folder) = folder {
+            let folder_dir = safe_resolve(&layout.secret_root, &folder)?;
+            let folder = folder_dir.strip_prefix(&layout.secret_root)
+                .map_err(|_| "Invalid folder".to_string())?
+                .to_string_lossy()
+                .to_string();
+            for relative in layout.age_files()? {
+                if in_folder(&relative, &folder) && !selected.contains(&relative) {
+                    selected.push(relative);
+                }
+            }
+        }
+        if selected.is_empty() {
+            return Err("No secrets selected for export".to_string());
+        }
+
+        let names = variable_names(&selected, &naming)?;
+        let mut vars: Vec<SecretField> = Vec::new();
+        for (i, (relative_path, key)) in selected.iter().zip(names).enumerate() {
+            tasks::progress(&format!("Decrypting {}", relative_path), i + 1, selected.len());
+            let file_path = safe_resolve(&layout.secret_root, relative_path)?;
+            let plaintext = age_cli::decrypt_file(&file_path, &identity_path)?;
+            vars.push(SecretField {
+                key,
+                value: plaintext.trim_end_matches('\n').to_string(),
+            });
+        }
+
+        let rendered = render_export(format, &vars)?;
+        let output = PathBuf::from(&output_path);
+        write_private(&output, &rendered)?;
+
+        let mut warnings = Vec::new();
+        let output_dir = output.parent()
+            .map(|p| p.canonicalize().unwrap_or_else(|_| p.to_path_buf()))
+            .unwrap_or_default();
+        if let Some(work_tree) = git_work_tree(&output_dir) {
+            warnings.push(format!(
+                "{} is inside the git working tree at {}; make sure it is gitignored",
+                output_path,
+                work_tree.display()
+            ));
+        }
+
+        Ok(ExportResult {
+            path: output_path,
+            count: vars.len(),
+            warnings,
+        })
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("thoughtseize-bulk-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_collect_import_items() {
+        let dir = temp_dir("import");
+        let env = dir.join(".env");
+        fs::write(&env, "# comment\nAPI_KEY=abc\nDB_URL=\"postgres://x\"\n").unwrap();
+        let items = collect_import_items(&env).unwrap();
+        let pairs: Vec<(&str, &str)> = items.iter().map(|i| (i.name.as_str(), i.content.as_str())).collect();
+        assert_eq!(pairs, vec![("API_KEY", "abc"), ("DB_URL", "postgres://x")]);
+
+        let source = dir.join("source");
+        fs::create_dir_all(source.join("web")).unwrap();
+        fs::write(source.join("token"), "t0k3n\n").unwrap();
+        fs::write(source.join("web/cert.pem"), "pem").unwrap();
+        fs::create_dir_all(source.join(".git")).unwrap();
+        fs::write(source.join(".git/config"), "[core]").unwrap();
+        let items = collect_import_items(&source).unwrap();
+        let pairs: Vec<(&str, &str)> = items.iter().map(|i| (i.name.as_str(), i.content.as_str())).collect();
+        assert_eq!(pairs, vec![("token", "t0k3n\n"), ("web/cert.pem", "pem")]);
+
+        assert!(collect_import_items(&dir.join("missing")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_in_folder() {
+        assert!(in_folder("web/db.age", "web"));
+        assert!(in_folder("web/api/key.age", "web"));
+        assert!(in_folder("top.age", ""));
+        assert!(!in_folder("webhooks/db.age", "web"));
+        assert!(!in_folder("web.age", "web"));
+    }
+
+    fn naming(style: NamingStyle, prefix: &str) -> ExportNaming {
+        ExportNaming { style, prefix: prefix.to_string(), uppercase: true }
+    }
+
+    #[test]
+    fn test_variable_names() {
+        let paths: Vec<String> = ["watch/gemini-api.key.age", "1password.age"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(variable_names(&paths, &naming(NamingStyle::Basename, "")).unwrap(), vec!["GEMINI_API_KEY", "_1PASSWORD"]);
+        assert_eq!(variable_names(&paths, &naming(NamingStyle::FullPath, "app_")).unwrap(), vec!["APP_WATCH_GEMINI_API_KEY", "APP_1PASSWORD"]);
+
+        let clashing: Vec<String> = ["a/TOKEN.age", "b/TOKEN.age"].iter().map(|s| s.to_string()).collect();
+        let err = variable_names(&clashing, &naming(NamingStyle::Basename, "")).unwrap_err();
+        assert!(err.contains("a/TOKEN.age and b/TOKEN.age both map to TOKEN"));
+        assert!(variable_names(&clashing, &naming(NamingStyle::FullPath, "")).is_ok());
+    }
+
+    #[test]
+    fn test_render_export() {
+        let vars = vec![
+            SecretField { key: "QUOTE".to_string(), value: "it's \"x\"".to_string() },
+            SecretField { key: "DOLLAR".to_string(), value: "$HOME `id` $(id)".to_string() },
+            SecretField { key: "MULTI".to_string(), value: "line1\nline2".to_string() },
+        ];
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        let shell = render_export(ExportFormat::Shell, &vars).unwrap();
+        assert_eq!(
+            shell,
+            "export QUOTE='it'\\''s \"x\"'\nexport DOLLAR='$HOME `id` $(id)'\nexport MULTI='line1\nline2'\n"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&render_export(ExportFormat::Json, &vars).unwrap()).unwrap();
+        assert_eq!(json["QUOTE"], "it's \"x\"");
+        assert_eq!(json["MULTI"], "line1\nline2");
+        let keys: Vec<&String> = json.as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["QUOTE", "DOLLAR", "MULTI"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("export");
+        let file = dir.join("out.env");
+        // An existing, world-readable file is narrowed too
+        fs::write(&file, "old").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&file, "A=1\n").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "A=1\n");
+        assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let created = dir.join("new.env");
+        write_private(&created, "B=2\n").unwrap();
+        assert_eq!(fs::metadata(&created).unwrap().permissions().mode() & 0o777, 0o600);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_apply_template() {
+        assert_eq!(apply_template("app/{name}.age", "API_KEY"), "app/API_KEY.age");
+        assert_eq!(apply_template("app/{name_lower}", "API_KEY"), "app/api_key.age");
+        assert_eq!(apply_template("{name}", "web/cert.pem"), "web/cert.pem.age");
+
+        // Source names can carry anything; such targets are rejected on import
+        let injected = apply_template("{name}", "x\"; evil = \"${builtins.abort \"\"}");
+        assert!(nix_eval::validate_secret_path(&injected).is_err());
+    }
+}
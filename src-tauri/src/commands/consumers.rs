@@ -0,0 +1,42 @@
+use tauri::AppHandle;
+use crate::consumers::{self, ConsumerReport};
+use crate::hosts::{self, HostCheck};
+use crate::keys;
+use crate::nix_eval;
+use crate::nix_parser;
+use super::tasks::run_task;
+
+/// Where each secret is used in the NixOS configuration: every `.nix` file
+/// in the repository that points at its `.age` file. Secrets with no
+/// consumers are listed in `unused`.
+#[tauri::command]
+pub async fn find_consumers(task_id: Option<String>, app: AppHandle) -> Result<ConsumerReport, String> {
+    run_task(app, task_id, |state| {
+        let layout = state.layout()?;
+        let parsed = nix_parser::parse_meta_secrets(&layout.read_declarations()?)?;
+        let mut paths = layout.age_files()?;
+        paths.extend(parsed.secrets.into_iter().map(|s| s.path));
+        paths.sort();
+        paths.dedup();
+        let roots = state.roots.lock()
+            .map_err(|_| "Internal state error".to_string())?
+            .clone();
+        consumers::report(&layout, &roots, &paths)
+    }).await
+}
+
+/// Check every host in the local flake's `nixosConfigurations` against the
+/// recipients of the secrets it declares, reporting secrets a host would fail
+/// to decrypt at activation. Evaluation is offline.
+#[tauri::command]
+pub async fn check_hosts(task_id: Option<String>, app: AppHandle) -> Result<HostCheck, String> {
+    run_task(app, task_id, |state| {
+        let layout = state.layout()?;
+        let flake_dir = layout.project_dir.ancestors()
+            .find(|dir| dir.join("flake.nix").exists())
+            .ok_or("No flake.nix found in or above the project")?;
+        let configs = nix_eval::evaluate_hosts(flake_dir)?;
+        let resolved = nix_eval::evaluate_declarations(&layout, &layout.read_declarations()?)?;
+        Ok(hosts::check(&configs, &resolved, &keys::load_labels(&layout)?, flake_dir, &layout.secret_root))
+    }).await
+}
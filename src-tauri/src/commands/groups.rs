@@ -0,0 +1,109 @@
+use tauri::AppHandle;
+use crate::access::{self, AccessChange, GroupList};
+use crate::keys;
+use crate::layout::ProjectLayout;
+use crate::nix_eval::{self, MetaEntry, ResolvedAccess};
+use crate::nix_parser;
+use crate::plan::{ChangePlan, FileOp, PlanPreview};
+use super::plans;
+use super::secrets::{is_valid_group_name, safe_resolve};
+use super::tasks::run_task;
+
+#[derive(serde::Serialize)]
+pub struct GroupChangePreview {
+    pub plan: PlanPreview,
+    /// Per secret, the keys that gain and lose access.
+    pub changes: Vec<AccessChange>,
+}
+
+/// Re-encrypt every existing secret whose recipients change. `new` is the
+/// access after the change; a secret left without recipients cannot be
+/// encrypted, so that is an error naming every such secret.
+pub(crate) fn rekey_changed(
+    plan: &mut ChangePlan,
+    layout: &ProjectLayout,
+    changes: &[AccessChange],
+    new: &ResolvedAccess,
+) -> Result<(), String> {
+    let mut orphaned: Vec<&str> = changes.iter()
+        .filter(|c| new.secrets.get(&c.path).is_none_or(|keys| keys.is_empty()))
+        .filter(|c| safe_resolve(&layout.secret_root, &c.path).is_ok_and(|f| f.exists()))
+        .map(|c| c.path.as_str())
+        .collect();
+    if !orphaned.is_empty() {
+        orphaned.sort();
+        return Err(format!(
+            "No recipients would be left for {}; trash these secrets or give them another recipient first",
+            orphaned.join(", ")
+        ));
+    }
+    for change in changes {
+        let file = safe_resolve(&layout.secret_root, &change.path)?;
+        if file.exists() {
+            plan.push(FileOp::Rekey { path: change.path.clone(), file });
+        }
+    }
+    Ok(())
+}
+
+/// Every group binding with the keys it resolves to and the members it is
+/// built from, evaluated in one `nix eval`. When evaluation fails, the
+/// groups are listed as written, without keys.
+#[tauri::command]
+pub async fn list_groups(task_id: Option<String>, app: AppHandle) -> Result<GroupList, String> {
+    run_task(app, task_id, |state| {
+        let layout = state.layout()?;
+        let declarations = layout.read_declarations()?;
+        let parsed = nix_parser::parse_meta_secrets(&declarations)?;
+        let (resolved, warning) = match nix_eval::evaluate_declarations(&layout, &declarations) {
+            Ok(resolved) => (resolved, None),
+            Err(e) => (ResolvedAccess::default(), Some(format!("Group keys could not be resolved: {}", e))),
+        };
+        Ok(GroupList {
+            groups: access::expand_groups(&parsed.groups, &resolved, &keys::load_labels(&layout)?),
+            warnings: warning.into_iter().collect(),
+        })
+    }).await
+}
+
+/// Attributes of the `meta` argument that hold keys, such as
+/// `meta.ssh.groups.TECH` or `meta.ssh.nodes.ci-runner`. Their paths can be
+/// used as groups when creating a secret.
+#[tauri::command]
+pub async fn meta_entries(task_id: Option<String>, app: AppHandle) -> Result<Vec<MetaEntry>, String> {
+    run_task(app, task_id, |state| {
+        let layout = state.layout()?;
+        nix_eval::meta_entries(&layout, &layout.read_declarations()?)
+    }).await
+}
+
+/// Change a group definition. Old and new recipients are evaluated first so
+/// the result lists who gains and who loses access to each secret; the
+/// affected secrets are re-encrypted. Unless `dry_run` is `false`, this is
+/// only a preview and the change is made by `apply_plan`.
+#[tauri::command]
+pub async fn update_group(
+    group: String,
+    definition: String,
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<GroupChangePreview, String> {
+    run_task(app, task_id, move |state| {
+        if !is_valid_group_name(&group) {
+            return Err(format!("Invalid group name: '{}'. Only alphanumeric and underscore allowed.", group));
+        }
+        let layout = state.layout()?;
+        let mut plan = ChangePlan::new(&layout)?;
+        let new_meta = nix_parser::set_group_definition(plan.base(), &group, &definition)?;
+
+        let old = nix_eval::evaluate_declarations(&layout, plan.base())?;
+        let new = nix_eval::evaluate_declarations(&layout, &new_meta)?;
+        let changes = access::diff(&old, &new, &keys::load_labels(&layout)?);
+
+        plan.set_declarations(new_meta);
+        rekey_changed(&mut plan, &layout, &changes, &new)?;
+        let plan = plans::submit(plan, Some(dry_run.unwrap_or(true)), state)?;
+        Ok(GroupChangePreview { plan, changes })
+    }).await
+}
@@ -0,0 +1,142 @@
+use tauri::AppHandle;
+use crate::access::{self, AccessChange};
+use crate::keys::{self, KeyInventory};
+use crate::nix_eval::{self, ResolvedAccess};
+use crate::nix_parser;
+use crate::plan::{ChangePlan, PlanPreview};
+use super::groups::rekey_changed;
+use super::secrets::is_valid_group_name;
+use super::plans;
+use super::tasks::run_task;
+
+/// Every public key in the declarations file, its imports and the `meta`
+/// argument. When evaluation fails, only the keys written literally in the
+/// declarations file are listed.
+#[tauri::command]
+pub async fn list_keys(task_id: Option<String>, app: AppHandle) -> Result<KeyInventory, String> {
+    run_task(app, task_id, |state| {
+        let layout = state.layout()?;
+        let declarations = layout.read_declarations()?;
+        let (resolved, warning) = match nix_eval::evaluate_declarations(&layout, &declarations) {
+            Ok(resolved) => (resolved, None),
+            Err(e) => (ResolvedAccess::default(), Some(format!("Showing only keys written in {}: {}", layout.declarations_name(), e))),
+        };
+        let literals = nix_parser::key_literals(&declarations);
+        let mut inventory = keys::inventory(&resolved, &literals, &keys::load_labels(&layout)?);
+        inventory.warnings.splice(0..0, warning);
+        Ok(inventory)
+    }).await
+}
+
+#[derive(serde::Serialize)]
+pub struct OffboardResult {
+    pub plan: PlanPreview,
+    pub changes: Vec<AccessChange>,
+    /// Secrets the key could decrypt before; their values should be rotated.
+    pub rotate: Vec<String>,
+    /// `let` bindings that were exactly this key and have been removed.
+    pub removed_bindings: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Secrets whose recipients include `key`, compared without key comments.
+fn readable_by(resolved: &ResolvedAccess, key: &str) -> Vec<String> {
+    let target = keys::normalize_key(key);
+    let mut paths: Vec<String> = resolved.secrets.iter()
+        .filter(|(_, recipients)| recipients.iter().any(|k| keys::normalize_key(k) == target))
+        .map(|(path, _)| path.clone())
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Remove a public key from every group and inline list, re-encrypt the
+/// secrets it loses access to, and list the secrets it could read so their
+/// values can be rotated. Unless `dry_run` is `false`, this is only a preview
+/// and the change is made by `apply_plan`.
+#[tauri::command]
+pub async fn offboard_key(
+    key: String,
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<OffboardResult, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+        let mut plan = ChangePlan::new(&layout)?;
+
+        let old = nix_eval::evaluate_declarations(&layout, plan.base())?;
+        let rotate = readable_by(&old, &key);
+        let (new_meta, removed_bindings) = nix_parser::remove_key(plan.base(), &key);
+        if new_meta == plan.base() && rotate.is_empty() {
+            return Err("That key does not appear in the declarations".to_string());
+        }
+
+        let new = nix_eval::evaluate_declarations(&layout, &new_meta)?;
+        let changes = access::diff(&old, &new, &keys::load_labels(&layout)?);
+        let warnings = readable_by(&new, &key).into_iter()
+            .map(|path| format!(
+                "{} is still readable by this key; it is granted outside {}",
+                path,
+                layout.declarations_name()
+            ))
+            .collect();
+
+        plan.set_declarations(new_meta);
+        rekey_changed(&mut plan, &layout, &changes, &new)?;
+        let plan = plans::submit(plan, Some(dry_run.unwrap_or(true)), state)?;
+        Ok(OffboardResult { plan, changes, rotate, removed_bindings, warnings })
+    }).await
+}
+
+#[derive(serde::Serialize)]
+pub struct OnboardResult {
+    pub plan: PlanPreview,
+    pub changes: Vec<AccessChange>,
+}
+
+/// Add a public key to the given groups and re-encrypt exactly the secrets
+/// whose recipients change as a result. An optional label is recorded in the
+/// project's key label file. Unless `dry_run` is `false`, this is only a
+/// preview and the change is made by `apply_plan`.
+#[tauri::command]
+pub async fn onboard_key(
+    key: String,
+    label: Option<String>,
+    groups: Vec<String>,
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<OnboardResult, String> {
+    run_task(app, task_id, move |state| {
+        let key = key.trim().to_string();
+        keys::validate_public_key(&key)?;
+        if groups.is_empty() {
+            return Err("At least one group is required".to_string());
+        }
+        for g in &groups {
+            if !is_valid_group_name(g) {
+                return Err(format!("Invalid group name: '{}'. Only alphanumeric and underscore allowed.", g));
+            }
+        }
+
+        let layout = state.layout()?;
+        let mut plan = ChangePlan::new(&layout)?;
+        let mut new_meta = plan.base().to_string();
+        for group in &groups {
+            new_meta = nix_parser::add_key_to_group(&new_meta, group, &key)?;
+        }
+
+        let old = nix_eval::evaluate_declarations(&layout, plan.base())?;
+        let new = nix_eval::evaluate_declarations(&layout, &new_meta)?;
+        let changes = access::diff(&old, &new, &keys::load_labels(&layout)?);
+
+        plan.set_declarations(new_meta);
+        if let Some(label) = label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty()) {
+            plan.set_label(&key, label);
+        }
+        rekey_changed(&mut plan, &layout, &changes, &new)?;
+        let plan = plans::submit(plan, Some(dry_run.unwrap_or(true)), state)?;
+        Ok(OnboardResult { plan, changes })
+    }).await
+}
@@ -1,3 +1,14 @@
 pub mod project;
 pub mod secrets;
 pub mod identity;
+pub mod bulk;
+pub mod plans;
+pub mod trash;
+pub mod rotation;
+pub mod tasks;
+pub mod settings;
+pub mod access;
+pub mod groups;
+pub mod keys;
+pub mod consumers;
+pub mod nixos;
@@ -0,0 +1,60 @@
+use std::fs;
+use tauri::AppHandle;
+use crate::consumers;
+use crate::nix_parser;
+use crate::nixos::{self, AgeSecretOptions};
+use crate::plan::{ChangePlan, FileOp, PlanPreview};
+use super::plans;
+use super::secrets::safe_resolve;
+use super::tasks::run_task;
+
+#[derive(serde::Serialize)]
+pub struct AgeSecretDeclaration {
+    pub name: String,
+    /// The declaration as a standalone snippet.
+    pub snippet: String,
+    /// The edit to `module_path`, when one was given.
+    pub plan: Option<PlanPreview>,
+}
+
+/// Generate the NixOS `age.secrets.<name>` declaration for a secret, with
+/// unset options taken from the saved defaults. With `module_path`, relative
+/// to the repository root, the file path is written relative to that module
+/// and the declaration is added to it.
+#[tauri::command]
+pub async fn age_secret_declaration(
+    relative_path: String,
+    options: Option<AgeSecretOptions>,
+    module_path: Option<String>,
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<AgeSecretDeclaration, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+        let secret = safe_resolve(&layout.secret_root, &relative_path)?;
+        let root = consumers::repository_root(&layout.project_dir);
+        let module = module_path.as_deref()
+            .map(|m| safe_resolve(&root, m))
+            .transpose()?;
+
+        let dir = module.as_deref().and_then(|m| m.parent()).unwrap_or(&root);
+        let name = nixos::secret_name(&relative_path);
+        let options = options.unwrap_or_default().or(&nixos::defaults());
+        let attrs = nixos::attrs(&nixos::relative_path_literal(dir, &secret), &name, &options);
+        let snippet = nixos::snippet(&name, &attrs);
+
+        let plan = match (module, module_path) {
+            (Some(file), Some(path)) => {
+                let base = fs::read_to_string(&file)
+                    .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+                let content = nix_parser::add_age_secret(&base, &name, &attrs)?;
+                let mut plan = ChangePlan::new(&layout)?;
+                plan.push(FileOp::Edit { path, file, base, content });
+                Some(plans::submit(plan, dry_run, state)?)
+            }
+            _ => None,
+        };
+        Ok(AgeSecretDeclaration { name, snippet, plan })
+    }).await
+}
@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, State};
+use crate::layout::ProjectLayout;
+use crate::state::{AppState, PendingPlan};
+use crate::nix_parser;
+use crate::plan::{ChangePlan, PlanPreview};
+use super::tasks::run_task;
+
+/// How long a previewed plan can still be applied.
+const PLAN_TTL: Duration = Duration::from_secs(15 * 60);
+
+fn next_plan_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("plan-{}", nanos)
+}
+
+/// Either apply a plan right away or, for a dry run, keep it for `apply_plan`.
+pub(crate) fn submit(
+    plan: ChangePlan,
+    dry_run: Option<bool>,
+    state: &AppState,
+) -> Result<PlanPreview, String> {
+    let layout = state.layout()?;
+    let preview = plan.preview(next_plan_id(), &layout);
+
+    if dry_run.unwrap_or(false) {
+        let mut pending = state.pending_plans.lock()
+            .map_err(|_| "Internal state error".to_string())?;
+        pending.retain(|_, p| p.created.elapsed() < PLAN_TTL);
+        pending.insert(preview.id.clone(), PendingPlan { layout, plan, created: Instant::now() });
+        return Ok(preview);
+    }
+
+    run(&plan, &layout, state)?;
+    Ok(preview)
+}
+
+/// Apply a plan to the secret root it was computed for, which need not be
+/// the one selected now.
+fn run(plan: &ChangePlan, layout: &ProjectLayout, state: &AppState) -> Result<(), String> {
+    let identity = state.identity_path().ok();
+    plan.apply(layout, identity.as_deref())?;
+
+    // Update cached state
+    if state.layout().ok().is_some_and(|current| current.declarations_file == layout.declarations_file) {
+        let mut parsed = state.parsed_secrets.lock()
+            .map_err(|_| "Internal state error".to_string())?;
+        *parsed = Some(nix_parser::parse_meta_secrets(plan.declarations())?);
+    }
+    Ok(())
+}
+
+/// Apply a change previously returned by a command called with `dry_run`.
+#[tauri::command]
+pub async fn apply_plan(
+    id: String,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<(), String> {
+    run_task(app, task_id, move |state| {
+        let pending = {
+            let mut pending = state.pending_plans.lock()
+                .map_err(|_| "Internal state error".to_string())?;
+            pending.retain(|_, p| p.created.elapsed() < PLAN_TTL);
+            pending.remove(&id)
+        };
+        let pending = pending.ok_or_else(|| format!("No pending change with id {}; preview it again", id))?;
+        run(&pending.plan, &pending.layout, state)
+    }).await
+}
+
+#[tauri::command]
+pub fn discard_plan(id: String, state: State<AppState>) -> Result<(), String> {
+    state.pending_plans.lock()
+        .map_err(|_| "Internal state error".to_string())?
+        .remove(&id);
+    Ok(())
+}
@@ -1,77 +1,215 @@
-use std::fs;
-use std::path::PathBuf;
-use tauri::State;
-use glob::glob;
-use crate::state::AppState;
-use crate::nix_parser;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use crate::keys;
+use crate::layout::{self, LayoutKind, LayoutOverrides, ProjectLayout};
+use crate::metadata::{self, SecretMetadata};
+use crate::nix_eval;
+use crate::nix_parser::{self, SecretEntry};
+use crate::status::{self, SecretStatus};
+use crate::tree::{self, SecretTree};
 use crate::config;
+use crate::state::{AppState, CachedRecipients};
+use super::secrets::is_valid_group_name;
+use super::tasks::run_task;
 
 #[derive(serde::Serialize)]
 pub struct ProjectInfo {
     pub path: String,
+    /// The selected secret root.
+    pub layout: ProjectLayout,
+    /// Every secret root found in `path`; `select_root` switches between them.
+    pub roots: Vec<ProjectLayout>,
     pub secrets: Vec<SecretFileInfo>,
+    /// The same secrets grouped into folders, with counts.
+    pub tree: SecretTree,
     pub groups: Vec<String>,
+    /// Why some status flags could not be computed.
+    pub warnings: Vec<String>,
 }
 
 #[derive(serde::Serialize)]
 pub struct SecretFileInfo {
     pub path: String,
     pub groups: Vec<String>,
+    pub armor: bool,
+    pub metadata: Option<SecretMetadata>,
+    pub status: SecretStatus,
 }
 
+/// Open a project. `layout` overrides the detected file names and secret
+/// root; when omitted, previously saved overrides for this directory apply
+/// and nested secret roots are discovered too, the top-most one selected.
 #[tauri::command]
-pub fn open_project(dir: String, state: State<AppState>) -> Result<ProjectInfo, String> {
+pub async fn open_project(
+    dir: String,
+    layout: Option<LayoutOverrides>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<ProjectInfo, String> {
+    run_task(app, task_id, move |state| open(state, dir, layout)).await
+}
+
+fn open(state: &AppState, dir: String, layout: Option<LayoutOverrides>) -> Result<ProjectInfo, String> {
     let project_dir = PathBuf::from(&dir);
 
-    // Find and parse meta_secrets.nix
-    let meta_path = project_dir.join("meta_secrets.nix");
-    if !meta_path.exists() {
-        return Err(format!("No meta_secrets.nix found in {}", dir));
-    }
+    let overrides = match layout {
+        Some(overrides) => {
+            layout::save_overrides(&dir, &overrides);
+            overrides
+        }
+        None => layout::load_overrides(&dir),
+    };
+    let roots = layout::discover(&project_dir, &overrides)?;
+    let info = load(state, dir, roots[0].clone(), roots)?;
+    *state.roots.lock()
+        .map_err(|_| "Internal state error".to_string())? = info.roots.clone();
+
+    config::save_config_value("project_path", &info.path);
+    Ok(info)
+}
 
-    let content = fs::read_to_string(&meta_path)
-        .map_err(|e| format!("Failed to read meta_secrets.nix: {}", e))?;
+/// Scan one secret root and make it the one commands act on.
+fn load(state: &AppState, dir: String, layout: ProjectLayout, roots: Vec<ProjectLayout>) -> Result<ProjectInfo, String> {
+    // Parse the file holding the publicKeys declarations
+    let content = layout.read_declarations()?;
     let parsed = nix_parser::parse_meta_secrets(&content)?;
+    let metadata = metadata::load(&layout)?;
 
-    // Scan for .age files
-    let pattern = project_dir.join("**/*.age").to_string_lossy().to_string();
-    let age_files: Vec<String> = glob(&pattern)
-        .map_err(|e| format!("Glob error: {}", e))?
-        .filter_map(|entry| entry.ok())
-        .filter_map(|path| {
-            path.strip_prefix(&project_dir)
-                .ok()
-                .map(|p| p.to_string_lossy().to_string())
-        })
-        .collect();
+    // Scan for .age files below the secret root
+    let age_files = layout.age_files()?;
 
-    let secrets: Vec<SecretFileInfo> = age_files.iter().map(|file_path| {
-        let groups = parsed.secrets.iter()
-            .find(|s| s.path == *file_path)
-            .map(|s| s.groups.clone())
-            .unwrap_or_default();
+    // Status flags come from the age headers, so nothing is decrypted or
+    // evaluated; recipients are compared only against the evaluation cached
+    // by `check_recipients`, while the declarations are unchanged
+    let identity_key = state.identity_path().ok()
+        .and_then(|identity| keys::identity_public_key(&identity).ok());
+    let declared: Vec<String> = parsed.secrets.iter().map(|s| s.path.clone()).collect();
+    let cached = state.recipients.lock()
+        .map_err(|_| "Internal state error".to_string())?;
+    let recipients = cached.as_ref()
+        .filter(|c| c.declarations_file == layout.declarations_file && c.declarations == content)
+        .map(|c| &c.secrets);
+    let (mut statuses, warnings) = status::compute(&layout, &age_files, &declared, recipients, identity_key.as_deref());
+    drop(cached);
+
+    // Declared secrets without a file are listed too, flagged as missing
+    let mut paths = age_files;
+    paths.extend(declared);
+    paths.sort();
+    paths.dedup();
+
+    let entries: HashMap<&str, &SecretEntry> = parsed.secrets.iter()
+        .map(|s| (s.path.as_str(), s))
+        .collect();
+    let secrets: Vec<SecretFileInfo> = paths.iter().map(|file_path| {
+        let entry = entries.get(file_path.as_str());
         SecretFileInfo {
             path: file_path.clone(),
-            groups,
+            groups: entry.map(|s| s.groups.clone()).unwrap_or_default(),
+            armor: entry.map(|s| s.armor).unwrap_or(false),
+            metadata: metadata.get(file_path).cloned(),
+            status: statuses.remove(file_path).unwrap_or_default(),
         }
     }).collect();
 
+    let tree = tree::build(&paths);
     let group_names: Vec<String> = parsed.groups.iter().map(|g| g.name.clone()).collect();
 
-    *state.project_dir.lock()
-        .map_err(|_| "Internal state error".to_string())? = Some(project_dir);
+    *state.layout.lock()
+        .map_err(|_| "Internal state error".to_string())? = Some(layout.clone());
+    // Previews were computed for the previous project and may hold plaintext
+    state.pending_plans.lock()
+        .map_err(|_| "Internal state error".to_string())?
+        .clear();
     *state.parsed_secrets.lock()
         .map_err(|_| "Internal state error".to_string())? = Some(parsed);
 
-    config::save_config_value("project_path", &dir);
-
     Ok(ProjectInfo {
         path: dir,
+        layout,
+        roots,
         secrets,
+        tree,
         groups: group_names,
+        warnings,
     })
 }
 
+/// Switch to another secret root of the open directory, given its
+/// `project_dir` as listed in `ProjectInfo::roots`.
+#[tauri::command]
+pub async fn select_root(
+    project_dir: String,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<ProjectInfo, String> {
+    run_task(app, task_id, move |state| {
+        let roots = state.roots.lock()
+            .map_err(|_| "Internal state error".to_string())?
+            .clone();
+        let layout = roots.iter()
+            .find(|r| r.project_dir == Path::new(&project_dir))
+            .cloned()
+            .ok_or_else(|| format!("{} is not a secret root of the open project", project_dir))?;
+        let dir = config::load_config_value("project_path").unwrap_or(project_dir);
+        load(state, dir, layout, roots)
+    }).await
+}
+
+/// Which secrets of the selected root were encrypted for other recipients
+/// than the rules now resolve to, by path. Evaluates the rules, so the UI
+/// asks for this separately from `open_project`; the evaluation is kept, and
+/// later `open_project` and `select_root` calls fill in
+/// `SecretStatus::recipients_stale` from it while the declarations are
+/// unchanged.
+#[tauri::command]
+pub async fn check_recipients(
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<HashMap<String, bool>, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+        let declarations = layout.read_declarations()?;
+        let resolved = nix_eval::evaluate_declarations(&layout, &declarations)?;
+        let stale = status::stale_recipients(&layout, &resolved.secrets);
+        *state.recipients.lock()
+            .map_err(|_| "Internal state error".to_string())? = Some(CachedRecipients {
+            declarations_file: layout.declarations_file.clone(),
+            declarations,
+            secrets: resolved.secrets,
+        });
+        Ok(stale)
+    }).await
+}
+
+/// Start a new project in `dir`: write the rules and declarations files for
+/// the chosen layout, with a `group` (default `admins`) holding the current
+/// identity's public key, then open it.
+#[tauri::command]
+pub async fn init_project(
+    dir: String,
+    kind: LayoutKind,
+    group: Option<String>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<ProjectInfo, String> {
+    run_task(app, task_id, move |state| {
+        let group = group.unwrap_or_else(|| "admins".to_string());
+        if !is_valid_group_name(&group) {
+            return Err(format!("Invalid group name: '{}'. Only alphanumeric and underscore allowed.", group));
+        }
+        let identity = state.identity_path()
+            .map_err(|_| "Choose an identity first; its public key seeds the first group".to_string())?;
+        let key = keys::identity_public_key(&identity)?;
+        keys::validate_public_key(&key)?;
+
+        layout::scaffold(&PathBuf::from(&dir), kind, &group, &key)?;
+        // Overrides saved for an earlier project in this directory no longer apply
+        open(state, dir, Some(LayoutOverrides::default()))
+    }).await
+}
+
 #[tauri::command]
 pub fn get_saved_project() -> Option<String> {
     let saved = config::load_config_value("project_path")?;
@@ -0,0 +1,21 @@
+use tauri::AppHandle;
+use crate::rotation::{self, RotationReport};
+use super::tasks::run_task;
+
+/// Days ahead of the due date at which a secret counts as due soon.
+pub const DEFAULT_SOON_DAYS: u32 = 14;
+
+/// Rotation status of every secret, from metadata and local git history.
+/// `default_interval_days` applies to secrets without their own interval.
+#[tauri::command]
+pub async fn rotation_report(
+    default_interval_days: Option<u32>,
+    soon_days: Option<u32>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<RotationReport, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+        rotation::report(&layout, default_interval_days, soon_days.unwrap_or(DEFAULT_SOON_DAYS))
+    }).await
+}
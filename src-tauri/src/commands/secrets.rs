@@ -1,12 +1,24 @@
-use std::fs;
 use std::path::{Path, PathBuf};
-use tauri::State;
+use tauri::{AppHandle, State};
 use crate::state::AppState;
 use crate::age_cli;
+use crate::formats::{self, SecretField, SecretFormat};
+use crate::metadata::SecretMetadata;
+use crate::nix_eval;
 use crate::nix_parser;
+use crate::plan::{ChangePlan, FileOp, PlanPreview};
+use crate::trash::{self, TrashEntry};
+use super::plans;
+use super::tasks::run_task;
+
+#[derive(serde::Serialize)]
+pub struct StructuredSecret {
+    pub format: SecretFormat,
+    pub fields: Vec<SecretField>,
+}
 
 /// Validate that a resolved path stays within the project directory.
-fn safe_resolve(project_dir: &Path, relative_path: &str) -> Result<PathBuf, String> {
+pub(crate) fn safe_resolve(project_dir: &Path, relative_path: &str) -> Result<PathBuf, String> {
     let resolved = project_dir.join(relative_path);
     // For existing files, canonicalize and check containment
     if resolved.exists() {
@@ -38,181 +50,302 @@ fn safe_resolve(project_dir: &Path, relative_path: &str) -> Result<PathBuf, Stri
     }
 }
 
+/// Resolve the path of a secret about to be declared. The path is quoted
+/// into the declarations, so it is checked like an evaluated one first.
+fn resolve_new_secret(secret_root: &Path, relative_path: &str) -> Result<PathBuf, String> {
+    nix_eval::validate_secret_path(relative_path)?;
+    safe_resolve(secret_root, relative_path)
+}
+
 /// Validate that a group name is a safe Nix identifier (alphanumeric + underscore).
-fn is_valid_group_name(name: &str) -> bool {
+pub(crate) fn is_valid_group_name(name: &str) -> bool {
     !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
 }
 
 #[tauri::command]
-pub fn decrypt_secret(relative_path: String, state: State<AppState>) -> Result<String, String> {
-    let project_dir = {
-        let guard = state.project_dir.lock()
-            .map_err(|_| "Internal state error".to_string())?;
-        guard.as_ref().ok_or("No project open")?.clone()
-    };
-
-    let identity_path = {
-        let guard = state.identity_path.lock()
-            .map_err(|_| "Internal state error".to_string())?;
-        guard.as_ref().ok_or("No identity configured. Click the gear icon to select an identity file.")?.clone()
-    };
-
-    let file_path = safe_resolve(&project_dir, &relative_path)?;
-    age_cli::decrypt_file(&file_path, &identity_path)
+pub async fn decrypt_secret(
+    relative_path: String,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<String, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+        let identity_path = state.identity_path()?;
+
+        let file_path = safe_resolve(&layout.secret_root, &relative_path)?;
+        age_cli::decrypt_file(&file_path, &identity_path)
+    }).await
 }
 
 #[tauri::command]
-pub fn save_secret(
+pub async fn save_secret(
     relative_path: String,
     content: String,
-    state: State<AppState>,
-) -> Result<(), String> {
-    let project_dir = {
-        let guard = state.project_dir.lock()
-            .map_err(|_| "Internal state error".to_string())?;
-        guard.as_ref().ok_or("No project open")?.clone()
-    };
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<PlanPreview, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
 
-    let file_path = safe_resolve(&project_dir, &relative_path)?;
+        let file_path = safe_resolve(&layout.secret_root, &relative_path)?;
 
-    // Resolve public keys by evaluating secrets.nix for this secret's path
-    let recipients = resolve_recipients(&project_dir, &relative_path)?;
+        // Refuse to store secrets that would not parse as their format
+        formats::validate_content(&relative_path, &content)?;
 
-    age_cli::encrypt_to_file(&content, &file_path, &recipients)
+        let mut plan = ChangePlan::new(&layout)?;
+        plan.push(FileOp::Write { path: relative_path, file: file_path, plaintext: content });
+        plans::submit(plan, dry_run, state)
+    }).await
 }
 
 #[tauri::command]
-pub fn create_secret(
+pub async fn decrypt_secret_fields(
+    relative_path: String,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<StructuredSecret, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+        let identity_path = state.identity_path()?;
+
+        let file_path = safe_resolve(&layout.secret_root, &relative_path)?;
+        let content = age_cli::decrypt_file(&file_path, &identity_path)?;
+
+        let format = formats::detect_format(&relative_path, &content);
+        let fields = match format {
+            SecretFormat::Raw => Vec::new(),
+            _ => formats::parse_fields(format, &content)?,
+        };
+        Ok(StructuredSecret { format, fields })
+    }).await
+}
+
+/// Apply field-level edits to a structured secret. The current plaintext is
+/// decrypted again so that comments and ordering survive the round trip.
+#[tauri::command]
+pub async fn save_secret_fields(
+    relative_path: String,
+    format: SecretFormat,
+    fields: Vec<SecretField>,
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<PlanPreview, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+        let identity_path = state.identity_path()?;
+
+        let file_path = safe_resolve(&layout.secret_root, &relative_path)?;
+        let current = age_cli::decrypt_file(&file_path, &identity_path)?;
+        let content = formats::apply_fields(format, &current, &fields)?;
+
+        let mut plan = ChangePlan::new(&layout)?;
+        plan.push(FileOp::Write { path: relative_path, file: file_path, plaintext: content });
+        plans::submit(plan, dry_run, state)
+    }).await
+}
+
+/// Create a secret readable by `groups`, which may be `let` bindings or
+/// attribute paths into the `meta` argument such as `meta.ssh.groups.TECH`.
+/// With `alias`, the groups are first bound to a new `let` name and the
+/// secret refers to that instead.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_secret(
     relative_path: String,
     content: String,
     groups: Vec<String>,
-    state: State<AppState>,
-) -> Result<(), String> {
-    // Validate all group names before doing anything
-    for g in &groups {
-        if !is_valid_group_name(g) {
-            return Err(format!("Invalid group name: '{}'. Only alphanumeric and underscore allowed.", g));
+    alias: Option<String>,
+    armor: Option<bool>,
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<PlanPreview, String> {
+    run_task(app, task_id, move |state| {
+        // Validate all group names before doing anything
+        for g in &groups {
+            if !nix_parser::is_key_reference(g) {
+                return Err(format!("Invalid group: '{}'. Use a binding name or an attribute path like meta.ssh.groups.TECH.", g));
+            }
+        }
+        let alias = alias.map(|a| a.trim().to_string()).filter(|a| !a.is_empty());
+        if let Some(ref alias) = alias {
+            if !is_valid_group_name(alias) {
+                return Err(format!("Invalid group name: '{}'. Only alphanumeric and underscore allowed.", alias));
+            }
+            if groups.is_empty() {
+                return Err("An alias needs at least one group".to_string());
+            }
         }
-    }
 
-    let project_dir = {
-        let guard = state.project_dir.lock()
-            .map_err(|_| "Internal state error".to_string())?;
-        guard.as_ref().ok_or("No project open")?.clone()
-    };
+        let layout = state.layout()?;
 
-    let file_path = safe_resolve(&project_dir, &relative_path)?;
+        let file_path = resolve_new_secret(&layout.secret_root, &relative_path)?;
 
-    // Create parent directories
-    if let Some(parent) = file_path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create directories: {}", e))?;
-    }
+        // The entry is written before encrypting so secrets.nix can resolve it
+        let mut plan = ChangePlan::new(&layout)?;
+        let mut new_meta = plan.base().to_string();
+        let groups = match alias {
+            Some(alias) => {
+                let definition = groups.join(" ++ ");
+                let parsed = nix_parser::parse_meta_secrets(&new_meta)?;
+                match parsed.groups.iter().find(|g| g.name == alias) {
+                    Some(existing) if existing.definition.trim() != definition => {
+                        return Err(format!("'{}' is already defined as {}", alias, existing.definition.trim()));
+                    }
+                    Some(_) => {}
+                    None => new_meta = nix_parser::set_group_definition(&new_meta, &alias, &definition)?,
+                }
+                vec![alias]
+            }
+            None => groups,
+        };
+        let group_refs: Vec<&str> = groups.iter().map(|s| s.as_str()).collect();
+        let new_meta = nix_parser::add_secret_entries(
+            &new_meta,
+            &[&relative_path],
+            &group_refs,
+            armor.unwrap_or(false),
+        );
+        plan.set_declarations(new_meta);
 
-    // 1. Add entry to meta_secrets.nix first so secrets.nix can resolve it
-    let meta_path = project_dir.join("meta_secrets.nix");
-    let original_meta = fs::read_to_string(&meta_path)
-        .map_err(|e| format!("Failed to read meta_secrets.nix: {}", e))?;
-    let group_refs: Vec<&str> = groups.iter().map(|s| s.as_str()).collect();
-    let new_meta = nix_parser::add_secret_entry(&original_meta, &relative_path, &group_refs);
-    fs::write(&meta_path, &new_meta)
-        .map_err(|e| format!("Failed to write meta_secrets.nix: {}", e))?;
-
-    // 2. Resolve recipients via secrets.nix (which imports meta_secrets.nix)
-    let recipients = match resolve_recipients(&project_dir, &relative_path) {
-        Ok(r) => r,
-        Err(e) => {
-            // Roll back meta_secrets.nix on failure
-            let _ = fs::write(&meta_path, &original_meta);
-            return Err(e);
-        }
-    };
+        let mut metadata = plan.metadata().clone();
+        let mut info = metadata.get(&relative_path).cloned().unwrap_or_default();
+        info.created.get_or_insert_with(chrono::Utc::now);
+        metadata.set(&relative_path, info);
+        plan.set_metadata(metadata);
 
-    // 3. Encrypt the file
-    if let Err(e) = age_cli::encrypt_to_file(&content, &file_path, &recipients) {
-        // Roll back meta_secrets.nix on failure
-        let _ = fs::write(&meta_path, &original_meta);
-        return Err(e);
-    }
+        plan.push(FileOp::Write { path: relative_path, file: file_path, plaintext: content });
+        plans::submit(plan, dry_run, state)
+    }).await
+}
+
+/// Delete a secret by moving its ciphertext and declaration into the project
+/// trash, from where `restore_secret` can bring it back.
+#[tauri::command]
+pub async fn delete_secret(
+    relative_path: String,
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<PlanPreview, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+
+        let file_path = safe_resolve(&layout.secret_root, &relative_path)?;
 
-    // Update cached state
-    let mut parsed = state.parsed_secrets.lock()
-        .map_err(|_| "Internal state error".to_string())?;
-    *parsed = Some(nix_parser::parse_meta_secrets(&new_meta)?);
+        let mut plan = ChangePlan::new(&layout)?;
+        let parsed = nix_parser::parse_meta_secrets(plan.base())?;
+        let declared = parsed.secrets.iter().find(|s| s.path == relative_path);
+        if declared.is_none() && !file_path.exists() {
+            return Err(format!("Secret not found: {}", relative_path));
+        }
+
+        let mut metadata = plan.metadata().clone();
+        let deleted_at = trash::now();
+        let entry = TrashEntry {
+            id: trash::new_id(&layout, &relative_path, deleted_at),
+            path: relative_path.clone(),
+            public_keys: declared.map(|s| s.raw_expr.clone()).unwrap_or_default(),
+            declaration: declared.map(|s| nix_parser::entry_source(plan.base(), s)).unwrap_or_default(),
+            deleted_at,
+            has_ciphertext: file_path.exists(),
+            metadata: metadata.remove(&relative_path),
+        };
 
-    Ok(())
+        let new_meta = nix_parser::remove_secret_entry(plan.base(), &relative_path);
+        plan.set_declarations(new_meta);
+        plan.set_metadata(metadata);
+        plan.push(FileOp::Trash { path: relative_path, file: file_path, entry });
+        plans::submit(plan, dry_run, state)
+    }).await
 }
 
+/// Rename a secret: its ciphertext, its declaration and its metadata move to
+/// the new path together.
 #[tauri::command]
-pub fn delete_secret(
-    relative_path: String,
-    state: State<AppState>,
-) -> Result<(), String> {
-    let project_dir = {
-        let guard = state.project_dir.lock()
-            .map_err(|_| "Internal state error".to_string())?;
-        guard.as_ref().ok_or("No project open")?.clone()
-    };
-
-    let file_path = safe_resolve(&project_dir, &relative_path)?;
-
-    // Remove .age file
-    if file_path.exists() {
-        fs::remove_file(&file_path)
-            .map_err(|e| format!("Failed to delete file: {}", e))?;
-    }
+pub async fn rename_secret(
+    from: String,
+    to: String,
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<PlanPreview, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+        // Both paths end up quoted in the declarations
+        nix_eval::validate_secret_path(&from)?;
+        nix_eval::validate_secret_path(&to)?;
 
-    // Remove from meta_secrets.nix
-    let meta_path = project_dir.join("meta_secrets.nix");
-    let meta_content = fs::read_to_string(&meta_path)
-        .map_err(|e| format!("Failed to read meta_secrets.nix: {}", e))?;
-    let new_content = nix_parser::remove_secret_entry(&meta_content, &relative_path);
-    fs::write(&meta_path, &new_content)
-        .map_err(|e| format!("Failed to write meta_secrets.nix: {}", e))?;
+        let file_path = safe_resolve(&layout.secret_root, &from)?;
+        let to_file = safe_resolve(&layout.secret_root, &to)?;
+        if !file_path.exists() {
+            return Err(format!("Secret not found: {}", from));
+        }
+        if to_file.exists() {
+            return Err(format!("{} already exists", to));
+        }
 
-    // Update cached state
-    let mut parsed = state.parsed_secrets.lock()
-        .map_err(|_| "Internal state error".to_string())?;
-    *parsed = Some(nix_parser::parse_meta_secrets(&new_content)?);
+        let mut plan = ChangePlan::new(&layout)?;
+        let new_meta = nix_parser::rename_secret_entry(plan.base(), &from, &to)?;
+        plan.set_declarations(new_meta);
 
-    Ok(())
+        let mut metadata = plan.metadata().clone();
+        metadata.rename(&from, &to);
+        plan.set_metadata(metadata);
+
+        plan.push(FileOp::Move { path: from, file: file_path, to_path: to, to_file });
+        plans::submit(plan, dry_run, state)
+    }).await
 }
 
-/// Resolve recipients for a secret by reading its publicKeys from secrets.nix via nix eval.
-/// This imports secrets.nix (which resolves all group definitions) and extracts the
-/// publicKeys attribute for the given secret path.
-fn resolve_recipients(project_dir: &Path, secret_path: &str) -> Result<Vec<String>, String> {
-    // Allowlist: only permit safe path characters (alphanumeric, ., _, /, -)
-    if !secret_path.chars().all(|c| c.is_alphanumeric() || "._/-".contains(c)) {
-        return Err("Invalid characters in secret path".to_string());
-    }
+/// Metadata recorded for a secret; empty when none has been set.
+#[tauri::command]
+pub fn get_secret_metadata(relative_path: String, state: State<AppState>) -> Result<SecretMetadata, String> {
+    let layout = state.layout()?;
+    Ok(crate::metadata::load(&layout)?
+        .get(&relative_path)
+        .cloned()
+        .unwrap_or_default())
+}
 
-    let secrets_nix = project_dir.join("secrets.nix");
-    if !secrets_nix.exists() {
-        return Err("secrets.nix not found in project directory".to_string());
-    }
+/// Replace a secret's metadata. Saving empty metadata removes its entry.
+#[tauri::command]
+pub fn update_secret_metadata(
+    relative_path: String,
+    metadata: SecretMetadata,
+    state: State<AppState>,
+) -> Result<SecretMetadata, String> {
+    let layout = state.layout()?;
+    let mut file = crate::metadata::load(&layout)?;
+    file.set(&relative_path, metadata.clone());
+    crate::metadata::save(&layout, &file)?;
+    Ok(metadata)
+}
 
-    // Import secrets.nix which fully resolves all group definitions,
-    // then index into the resulting attrset by the secret's path to get publicKeys.
-    let output = std::process::Command::new("nix")
-        .args(["eval", "--impure", "--json", "--expr"])
-        .arg(format!(
-            "(import {}/secrets.nix).\"{}\".publicKeys",
-            project_dir.display(),
-            secret_path
-        ))
-        .current_dir(project_dir)
-        .output()
-        .map_err(|e| format!("Failed to run nix eval: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("nix eval failed: {}", stderr));
-    }
+/// Record that a secret was rotated just now.
+#[tauri::command]
+pub fn mark_secret_rotated(relative_path: String, state: State<AppState>) -> Result<SecretMetadata, String> {
+    let layout = state.layout()?;
+    let mut file = crate::metadata::load(&layout)?;
+    let mut metadata = file.get(&relative_path).cloned().unwrap_or_default();
+    metadata.rotated = Some(chrono::Utc::now());
+    file.set(&relative_path, metadata.clone());
+    crate::metadata::save(&layout, &file)?;
+    Ok(metadata)
+}
 
-    let json_str = String::from_utf8_lossy(&output.stdout);
-    let keys: Vec<String> = serde_json::from_str(&json_str)
-        .map_err(|e| format!("Failed to parse nix eval output: {}", e))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(keys)
+    #[test]
+    fn test_resolve_new_secret() {
+        let root = std::env::temp_dir();
+        assert_eq!(resolve_new_secret(&root, "web/db.age").unwrap(), root.join("web/db.age"));
+        assert!(resolve_new_secret(&root, "x\"; evil = \"${builtins.abort \"\"}.age").is_err());
+        assert!(resolve_new_secret(&root, "a b.age").is_err());
+        assert!(resolve_new_secret(&root, "../outside.age").is_err());
+    }
 }
@@ -0,0 +1,27 @@
+use crate::nixos::{self, AgeSecretOptions};
+use crate::process::{self, Timeouts};
+
+#[tauri::command]
+pub fn get_timeouts() -> Timeouts {
+    process::timeouts()
+}
+
+/// Set the time limits for `nix`, `age` and `git`; 0 disables a limit.
+#[tauri::command]
+pub fn set_timeouts(timeouts: Timeouts) -> Timeouts {
+    process::save_timeouts(&timeouts);
+    timeouts
+}
+
+#[tauri::command]
+pub fn get_age_secret_defaults() -> AgeSecretOptions {
+    nixos::defaults()
+}
+
+/// Set the owner, group, mode and path used for generated `age.secrets`
+/// declarations when a request leaves them unset.
+#[tauri::command]
+pub fn set_age_secret_defaults(defaults: AgeSecretOptions) -> AgeSecretOptions {
+    nixos::save_defaults(&defaults);
+    defaults
+}
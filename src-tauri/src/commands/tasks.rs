@@ -0,0 +1,50 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use tauri::{AppHandle, Emitter, Manager, State};
+use crate::state::AppState;
+use crate::tasks::{self, Task, TaskProgress};
+
+static NEXT_TASK: AtomicU64 = AtomicU64::new(1);
+
+/// Run a command body on the blocking thread pool as a cancellable task.
+/// Progress is emitted as `task-progress` events carrying the task id; the
+/// frontend may pass its own id so it can cancel before the call returns.
+pub(crate) async fn run_task<T, F>(app: AppHandle, task_id: Option<String>, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&AppState) -> Result<T, String> + Send + 'static,
+{
+    let id = task_id.unwrap_or_else(|| format!("task-{}", NEXT_TASK.fetch_add(1, Ordering::Relaxed)));
+    let emitter = app.clone();
+    let task = Task::new(id.clone(), Some(Box::new(move |progress: &TaskProgress| {
+        let _ = emitter.emit("task-progress", progress.clone());
+    })));
+    app.state::<AppState>().tasks.lock()
+        .map_err(|_| "Internal state error".to_string())?
+        .insert(id.clone(), task.clone());
+
+    let worker = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let state = worker.state::<AppState>();
+        tasks::with_task(task, || f(&state))
+    }).await;
+
+    if let Ok(mut running) = app.state::<AppState>().tasks.lock() {
+        running.remove(&id);
+    }
+    result.map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Cancel a running task, killing any `nix` or `age` process it is waiting on.
+/// Returns false when no such task is running.
+#[tauri::command]
+pub fn cancel_task(task_id: String, state: State<AppState>) -> Result<bool, String> {
+    let running = state.tasks.lock()
+        .map_err(|_| "Internal state error".to_string())?;
+    match running.get(&task_id) {
+        Some(task) => {
+            task.cancel();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
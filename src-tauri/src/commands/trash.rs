@@ -0,0 +1,65 @@
+use tauri::{AppHandle, State};
+use crate::state::AppState;
+use crate::nix_parser;
+use crate::plan::{ChangePlan, FileOp, PlanPreview};
+use crate::trash::{self, TrashEntry};
+use super::plans;
+use super::secrets::safe_resolve;
+use super::tasks::run_task;
+
+#[tauri::command]
+pub fn list_trash(state: State<AppState>) -> Result<Vec<TrashEntry>, String> {
+    let layout = state.layout()?;
+    Ok(trash::list(&layout))
+}
+
+/// Put a trashed secret back: its ciphertext returns to the original path and
+/// its declaration is appended to the declarations file again.
+#[tauri::command]
+pub async fn restore_secret(
+    id: String,
+    dry_run: Option<bool>,
+    task_id: Option<String>,
+    app: AppHandle,
+) -> Result<PlanPreview, String> {
+    run_task(app, task_id, move |state| {
+        let layout = state.layout()?;
+        let entry = trash::load(&layout, &id)?;
+        let file_path = safe_resolve(&layout.secret_root, &entry.path)?;
+
+        let mut plan = ChangePlan::new(&layout)?;
+        let parsed = nix_parser::parse_meta_secrets(plan.base())?;
+        if parsed.secrets.iter().any(|s| s.path == entry.path) {
+            return Err(format!("{} is already declared; delete or rename it first", entry.path));
+        }
+        if file_path.exists() {
+            return Err(format!("{} already exists", entry.path));
+        }
+
+        if !entry.declaration.trim().is_empty() {
+            let new_meta = nix_parser::append_statements(plan.base(), &entry.declaration);
+            plan.set_declarations(new_meta);
+        }
+        if let Some(info) = entry.metadata {
+            let mut metadata = plan.metadata().clone();
+            metadata.set(&entry.path, info);
+            plan.set_metadata(metadata);
+        }
+        plan.push(FileOp::Restore { path: entry.path, file: file_path, id });
+        plans::submit(plan, dry_run, state)
+    }).await
+}
+
+/// Permanently delete trashed secrets; all of them when `ids` is omitted.
+#[tauri::command]
+pub fn empty_trash(ids: Option<Vec<String>>, state: State<AppState>) -> Result<usize, String> {
+    let layout = state.layout()?;
+    let ids = match ids {
+        Some(ids) => ids,
+        None => trash::list(&layout).into_iter().map(|e| e.id).collect(),
+    };
+    for id in &ids {
+        trash::remove(&layout, id)?;
+    }
+    Ok(ids.len())
+}
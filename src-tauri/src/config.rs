@@ -31,3 +31,17 @@ pub fn save_config_value(key: &str, value: &str) {
     config[key] = serde_json::Value::String(value.to_string());
     save_config(&config);
 }
+
+/// Load a structured (non-string) config value.
+pub fn load_config_entry<T: serde::de::DeserializeOwned>(key: &str) -> Option<T> {
+    let config = load_config();
+    serde_json::from_value(config.get(key)?.clone()).ok()
+}
+
+pub fn save_config_entry<T: serde::Serialize>(key: &str, value: &T) {
+    let mut config = load_config();
+    if let Ok(value) = serde_json::to_value(value) {
+        config[key] = value;
+        save_config(&config);
+    }
+}
@@ -0,0 +1,276 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use serde::Serialize;
+use crate::layout::{self, ProjectLayout};
+use crate::nix_eval;
+
+/// A line in a `.nix` file that points at a secret.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Consumer {
+    /// Relative to the repository root.
+    pub file: String,
+    /// 1-based.
+    pub line: usize,
+    /// The `age.secrets.<name>` attribute the reference belongs to, if found.
+    pub name: Option<String>,
+    /// Whether the reference is a path literal that resolves to the secret,
+    /// rather than an interpolated string matched by its ending.
+    pub exact: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SecretConsumers {
+    pub path: String,
+    pub consumers: Vec<Consumer>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct ConsumerReport {
+    pub secrets: Vec<SecretConsumers>,
+    /// Secrets nothing refers to.
+    pub unused: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// The enclosing git checkout, or the project directory outside of one.
+pub fn repository_root(project_dir: &Path) -> PathBuf {
+    project_dir.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(project_dir)
+        .to_path_buf()
+}
+
+/// Resolve `.` and `..` without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// The `.age` path expressions on a line: path literals like
+/// `../secrets/db.age` and strings like `"${self}/secrets/db.age"`, each with
+/// whether it sits inside a string.
+fn age_paths(line: &str) -> Vec<(&str, bool)> {
+    let is_end = |c: Option<char>| c.is_none_or(|c| c.is_whitespace() || "\"';)]}".contains(c));
+    let mut found = Vec::new();
+    for (end, _) in line.match_indices(".age") {
+        let end = end + ".age".len();
+        if !is_end(line[end..].chars().next()) {
+            continue;
+        }
+        let start = line[..end]
+            .rfind(|c: char| c.is_whitespace() || "\"'=;([".contains(c))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        if end - start > ".age".len() {
+            let quotes = line[..start].matches('"').count() - line[..start].matches("\\\"").count();
+            found.push((&line[start..end], quotes % 2 == 1));
+        }
+    }
+    found
+}
+
+/// The secret name from the nearest `age.secrets.<name>` (or `secrets.<name>`
+/// inside an `age = { … }` block) at or above `line`.
+fn secret_name(lines: &[&str], line: usize) -> Option<String> {
+    for text in lines[..=line].iter().rev().take(6) {
+        if let Some(i) = text.find("secrets.") {
+            let rest = &text[i + "secrets.".len()..];
+            let name = match rest.strip_prefix('"') {
+                Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+                None => rest.split(|c: char| !(c.is_alphanumeric() || "_-'".contains(c))).next().unwrap_or_default(),
+            };
+            return (!name.is_empty()).then(|| name.to_string());
+        }
+    }
+    None
+}
+
+/// The right-hand side of a `file = …` binding that belongs to an
+/// `age.secrets` declaration: `age.secrets.<name>.file = …`, or `file = …`
+/// inside an `age.secrets` attrset a few lines up.
+fn secret_file_binding<'a>(lines: &[&str], line: usize, code: &'a str) -> Option<&'a str> {
+    let (lhs, rhs) = code.split_once('=')?;
+    let lhs = lhs.trim();
+    let declares_secret = |text: &str| text.contains("age.secrets") || text.trim_start().starts_with("secrets.");
+    let found = match lhs.strip_suffix(".file") {
+        Some(attr) => declares_secret(attr),
+        None => lhs == "file" && lines[..line].iter().rev().take(5).any(|l| declares_secret(l)),
+    };
+    found.then_some(rhs)
+}
+
+/// Find the references to `secrets` in one file. `secrets` pairs each secret
+/// path with its absolute location; `suffixes` are the endings an
+/// interpolated string may match, longest first. Only the `file` of an
+/// `age.secrets` declaration counts as a reference.
+fn scan_file(
+    file: &str,
+    dir: &Path,
+    content: &str,
+    secrets: &[(String, PathBuf)],
+    suffixes: &[(String, usize)],
+) -> Vec<(usize, Consumer)> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut found = Vec::new();
+    for (idx, line) in lines.iter().enumerate() {
+        let code = line.split('#').next().unwrap_or_default();
+        let Some(rhs) = secret_file_binding(&lines, idx, code) else { continue };
+        for (expr, in_string) in age_paths(rhs) {
+            // A fragment like `"/db.age"` in `../secrets + "/db.age"` is a string,
+            // not an absolute path, and is matched by its ending
+            let literal = !in_string && !expr.contains("${")
+                && (expr.starts_with("./") || expr.starts_with("../") || expr.starts_with('/'));
+            let target = if literal {
+                let resolved = normalize(&dir.join(expr));
+                secrets.iter().position(|(_, abs)| *abs == resolved)
+            } else {
+                let tail = expr.rsplit('}').next().unwrap_or(expr).trim_start_matches('/');
+                suffixes.iter()
+                    .find(|(suffix, _)| tail == suffix || tail.ends_with(&format!("/{}", suffix)))
+                    .map(|(_, i)| *i)
+            };
+            if let Some(i) = target {
+                found.push((i, Consumer {
+                    file: file.to_string(),
+                    line: idx + 1,
+                    name: secret_name(&lines, idx),
+                    exact: literal,
+                }));
+            }
+        }
+    }
+    found
+}
+
+/// Scan every `.nix` file in the repository that `.gitignore` does not
+/// exclude for references to each secret of `layout`. The rules and
+/// declarations files of every root in `roots` are skipped: they declare
+/// secrets rather than consume them.
+pub fn report(layout: &ProjectLayout, roots: &[ProjectLayout], paths: &[String]) -> Result<ConsumerReport, String> {
+    let root = repository_root(&layout.project_dir);
+    let secrets: Vec<(String, PathBuf)> = paths.iter()
+        .map(|p| (p.clone(), normalize(&layout.secret_root.join(p))))
+        .collect();
+    // Interpolated strings are matched against the path below the secret root
+    // and below the repository root, longest first
+    let mut suffixes: Vec<(String, usize)> = Vec::new();
+    for (i, (path, abs)) in secrets.iter().enumerate() {
+        suffixes.push((path.clone(), i));
+        if let Ok(rel) = abs.strip_prefix(&root) {
+            suffixes.push((rel.to_string_lossy().to_string(), i));
+        }
+    }
+    suffixes.sort_by_key(|(s, _)| std::cmp::Reverse(s.len()));
+
+    let files = layout::walk(&root, |_| true)
+        .filter(|path| path.extension().is_some_and(|e| e == "nix"))
+        // Declarations copied aside while another command evaluates them
+        .filter(|path| !path.file_name().is_some_and(|n| n.to_string_lossy().starts_with(nix_eval::EVAL_FILE_PREFIX)));
+    let skipped: Vec<PathBuf> = std::iter::once(layout).chain(roots)
+        .flat_map(|r| [normalize(&r.rules_file), normalize(&r.declarations_file)])
+        .collect();
+
+    let mut report = ConsumerReport::default();
+    let mut consumers: Vec<Vec<Consumer>> = vec![Vec::new(); secrets.len()];
+    for file in files {
+        let Ok(rel) = file.strip_prefix(&root) else { continue };
+        let name = rel.to_string_lossy().to_string();
+        if skipped.contains(&normalize(&file)) {
+            continue;
+        }
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) => {
+                report.warnings.push(format!("Failed to read {}: {}", name, e));
+                continue;
+            }
+        };
+        let dir = normalize(file.parent().unwrap_or(&root));
+        for (i, consumer) in scan_file(&name, &dir, &content, &secrets, &suffixes) {
+            consumers[i].push(consumer);
+        }
+    }
+
+    for ((path, _), consumers) in secrets.into_iter().zip(consumers) {
+        if consumers.is_empty() {
+            report.unused.push(path.clone());
+        }
+        report.secrets.push(SecretConsumers { path, consumers });
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_age_paths() {
+        assert_eq!(age_paths("  age.secrets.db.file = ../secrets/db.age;"), vec![("../secrets/db.age", false)]);
+        assert_eq!(age_paths("file = \"${self}/secrets/db.age\";"), vec![("${self}/secrets/db.age", true)]);
+        assert_eq!(age_paths("file = ../secrets + \"/db.age\";"), vec![("/db.age", true)]);
+        assert_eq!(age_paths("file = /etc/secrets/db.age;"), vec![("/etc/secrets/db.age", false)]);
+        assert!(age_paths("file = ./secrets/db.age.bak;").is_empty());
+    }
+
+    #[test]
+    fn test_scan_file() {
+        let secrets = vec![
+            ("db.age".to_string(), PathBuf::from("/repo/secrets/db.age")),
+            ("web/db.age".to_string(), PathBuf::from("/repo/secrets/web/db.age")),
+        ];
+        let suffixes = vec![
+            ("secrets/web/db.age".to_string(), 1),
+            ("secrets/db.age".to_string(), 0),
+            ("web/db.age".to_string(), 1),
+            ("db.age".to_string(), 0),
+        ];
+        let content = "{ self, ... }:\n{\n  age.secrets.db.file = ../../secrets/db.age;\n  age.secrets.\"web-db\" = {\n    file = \"${self}/secrets/web/db.age\";\n  };\n  # age.secrets.old.file = ../../secrets/db.age;\n  age.secrets.joined.file = ../../secrets + \"/db.age\";\n  environment.etc.\"note\".text = \"see secrets/db.age\";\n  backup.file = \"/repo/secrets/db.age\";\n}\n";
+        let found = scan_file("hosts/web/default.nix", Path::new("/repo/hosts/web"), content, &secrets, &suffixes);
+
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0], (0, Consumer {
+            file: "hosts/web/default.nix".to_string(),
+            line: 3,
+            name: Some("db".to_string()),
+            exact: true,
+        }));
+        assert_eq!(found[1].0, 1);
+        assert_eq!(found[1].1.name.as_deref(), Some("web-db"));
+        assert!(!found[1].1.exact);
+        assert_eq!(found[2].0, 0);
+        assert_eq!(found[2].1.name.as_deref(), Some("joined"));
+        assert!(!found[2].1.exact);
+    }
+
+    #[test]
+    fn test_report_skips_every_root() {
+        let dir = std::env::temp_dir().join(format!("thoughtseize-consumers-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let key = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAINu6Q";
+        layout::scaffold(&dir, layout::LayoutKind::SecretsNix, "admins", key).unwrap();
+        layout::scaffold(&dir.join("team"), layout::LayoutKind::SecretsNix, "admins", key).unwrap();
+        // The nested root declares a secret of the same name
+        let team_rules = dir.join("team/secrets.nix");
+        let rules = fs::read_to_string(&team_rules).unwrap();
+        fs::write(&team_rules, crate::nix_parser::add_secret_entry(&rules, "db.age", &["admins"])).unwrap();
+        fs::create_dir_all(dir.join("hosts")).unwrap();
+        fs::write(dir.join("hosts/web.nix"), "{\n  age.secrets.db.file = ../db.age;\n  motd = \"db.age\";\n}\n").unwrap();
+
+        let roots = layout::discover(&dir, &layout::LayoutOverrides::default()).unwrap();
+        assert_eq!(roots.len(), 2);
+        let report = report(&roots[0], &roots, &["db.age".to_string()]).unwrap();
+        let files: Vec<(&str, usize)> = report.secrets[0].consumers.iter().map(|c| (c.file.as_str(), c.line)).collect();
+        assert_eq!(files, vec![("hosts/web.nix", 2)]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
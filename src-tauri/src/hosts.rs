@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::Serialize;
+use crate::access::LabelledKey;
+use crate::keys::{self, KeyLabels};
+use crate::nix_eval::{HostConfig, ResolvedAccess};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HostProblemKind {
+    /// The host key is not among the secret's recipients.
+    NotRecipient,
+    /// The file is not declared in the rules at all.
+    NotDeclared,
+}
+
+/// A secret a host uses but will fail to decrypt at activation.
+#[derive(Debug, Serialize, Clone)]
+pub struct HostProblem {
+    pub host: String,
+    /// The `age.secrets.<name>` attribute.
+    pub name: String,
+    /// `file` as the host configuration evaluates it.
+    pub file: String,
+    /// The matching secret path, when declared.
+    pub path: Option<String>,
+    pub kind: HostProblemKind,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HostSummary {
+    /// The `nixosConfigurations` attribute.
+    pub name: String,
+    pub host_name: String,
+    pub keys: Vec<LabelledKey>,
+    pub secrets: usize,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct HostCheck {
+    pub hosts: Vec<HostSummary>,
+    pub problems: Vec<HostProblem>,
+    pub warnings: Vec<String>,
+}
+
+/// A secret file path below the flake: store paths lose their
+/// `/nix/store/<hash>-<name>/` prefix, other absolute paths the repository
+/// root.
+fn relative_file<'a>(file: &'a str, root: &Path) -> &'a str {
+    if let Some(rest) = file.strip_prefix("/nix/store/") {
+        return rest.split_once('/').map(|(_, tail)| tail).unwrap_or(rest);
+    }
+    Path::new(file).strip_prefix(root).ok()
+        .and_then(|p| p.to_str())
+        .unwrap_or(file)
+}
+
+/// Keys that belong to a host: its `knownHosts` entries, and any recipient
+/// labelled with the host name or commented `<user>@<host>`.
+fn host_keys(config: &HostConfig, all_keys: &[&String], labels: &KeyLabels) -> Vec<String> {
+    let host = config.host_name.to_lowercase();
+    let mut found: Vec<String> = config.known_host_keys.iter().map(|k| keys::normalize_key(k)).collect();
+    for key in all_keys {
+        let label = labels.get(&keys::normalize_key(key)).map(|l| l.to_lowercase());
+        let comment = keys::key_comment(key).map(|c| c.to_lowercase());
+        let comment_host = comment.as_deref().map(|c| c.rsplit('@').next().unwrap_or(c));
+        if label.as_deref() == Some(host.as_str()) || comment_host == Some(host.as_str()) {
+            found.push(keys::normalize_key(key));
+        }
+    }
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// Compare the secrets each host declares with the recipients in the rules.
+/// `root` is the flake directory and `secret_root` the directory secret paths
+/// are relative to.
+pub fn check(
+    hosts: &HashMap<String, HostConfig>,
+    resolved: &ResolvedAccess,
+    labels: &KeyLabels,
+    root: &Path,
+    secret_root: &Path,
+) -> HostCheck {
+    let prefix = secret_root.strip_prefix(root).ok()
+        .map(|p| p.to_string_lossy().to_string())
+        .filter(|p| !p.is_empty());
+    let declared_path = |file: &str| -> Option<String> {
+        resolved.secrets.keys()
+            .find(|path| {
+                let from_root = match &prefix {
+                    Some(prefix) => format!("{}/{}", prefix, path),
+                    None => path.to_string(),
+                };
+                file == from_root || file == path.as_str() || file.ends_with(&format!("/{}", from_root))
+            })
+            .cloned()
+    };
+    let mut all_keys: Vec<&String> = resolved.secrets.values().chain(resolved.groups.values()).flatten().collect();
+    all_keys.sort();
+    all_keys.dedup();
+
+    let mut names: Vec<&String> = hosts.keys().collect();
+    names.sort();
+    let mut report = HostCheck::default();
+    for name in names {
+        let config = &hosts[name];
+        let Some(secrets) = &config.secrets else {
+            report.warnings.push(format!("{}: age.secrets could not be evaluated", name));
+            continue;
+        };
+        let host_keys = host_keys(config, &all_keys, labels);
+        report.hosts.push(HostSummary {
+            name: name.clone(),
+            host_name: config.host_name.clone(),
+            keys: host_keys.iter()
+                .map(|k| LabelledKey { key: k.clone(), label: keys::label_for(k, labels) })
+                .collect(),
+            secrets: secrets.len(),
+        });
+        if secrets.is_empty() {
+            continue;
+        }
+        if host_keys.is_empty() {
+            report.warnings.push(format!(
+                "{}: no host key found; add it to programs.ssh.knownHosts or label it {}",
+                name, config.host_name
+            ));
+            continue;
+        }
+
+        let mut secret_names: Vec<&String> = secrets.keys().collect();
+        secret_names.sort();
+        for secret in secret_names {
+            let file = &secrets[secret];
+            let path = declared_path(relative_file(file, root));
+            let kind = match &path {
+                None => HostProblemKind::NotDeclared,
+                Some(path) if !resolved.secrets[path].iter().any(|k| host_keys.contains(&keys::normalize_key(k))) => {
+                    HostProblemKind::NotRecipient
+                }
+                Some(_) => continue,
+            };
+            report.problems.push(HostProblem {
+                host: name.clone(),
+                name: secret.clone(),
+                file: file.clone(),
+                path,
+                kind,
+            });
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut resolved = ResolvedAccess::default();
+        resolved.secrets.insert("db.age".to_string(), vec!["ssh-ed25519 AAAAweb root@web".to_string()]);
+        resolved.secrets.insert("api.age".to_string(), vec!["ssh-ed25519 AAAAalice alice".to_string()]);
+
+        let web = HostConfig {
+            host_name: "web".to_string(),
+            secrets: Some(HashMap::from([
+                ("db".to_string(), "/nix/store/abc-source/secrets/db.age".to_string()),
+                ("api".to_string(), "/repo/secrets/api.age".to_string()),
+                ("old".to_string(), "/nix/store/abc-source/secrets/old.age".to_string()),
+            ])),
+            known_host_keys: Vec::new(),
+        };
+        let broken = HostConfig { host_name: "db".to_string(), secrets: None, known_host_keys: Vec::new() };
+        let hosts = HashMap::from([("web".to_string(), web), ("db".to_string(), broken)]);
+
+        let report = check(&hosts, &resolved, &KeyLabels::new(), Path::new("/repo"), Path::new("/repo/secrets"));
+        assert_eq!(report.hosts.len(), 1);
+        assert_eq!(report.hosts[0].keys[0].key, "ssh-ed25519 AAAAweb");
+        assert_eq!(report.warnings, vec!["db: age.secrets could not be evaluated"]);
+
+        assert_eq!(report.problems.len(), 2);
+        assert_eq!(report.problems[0].name, "api");
+        assert_eq!(report.problems[0].path.as_deref(), Some("api.age"));
+        assert_eq!(report.problems[0].kind, HostProblemKind::NotRecipient);
+        assert_eq!(report.problems[1].name, "old");
+        assert_eq!(report.problems[1].kind, HostProblemKind::NotDeclared);
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use base64::Engine;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::layout::ProjectLayout;
+use crate::nix_eval::ResolvedAccess;
+use crate::nix_parser::KeyLiteral;
+
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const MIN_RSA_BITS: u32 = 2048;
+
+/// Names for recipient keys, keyed by the key without its comment. Kept in
+/// the project's `.thoughtseize` directory next to the secret metadata.
+pub type KeyLabels = BTreeMap<String, String>;
+
+pub fn labels_path(layout: &ProjectLayout) -> PathBuf {
+    layout.project_dir.join(".thoughtseize").join("key-labels.json")
+}
+
+pub fn load_labels(layout: &ProjectLayout) -> Result<KeyLabels, String> {
+    let path = labels_path(layout);
+    if !path.exists() {
+        return Ok(KeyLabels::new());
+    }
+    let json = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&json)
+        .map_err(|e| format!("Invalid key label file {}: {}", path.display(), e))
+}
+
+pub fn save_labels(layout: &ProjectLayout, labels: &KeyLabels) -> Result<(), String> {
+    let path = labels_path(layout);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let json = serde_json::to_string_pretty(labels)
+        .map_err(|e| format!("Failed to serialize key labels: {}", e))?;
+    fs::write(&path, json + "\n")
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// The key without its comment: `ssh-ed25519 AAAA... user@host` becomes
+/// `ssh-ed25519 AAAA...`; age keys are returned unchanged.
+pub fn normalize_key(key: &str) -> String {
+    let mut parts = key.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some(kind), Some(data)) if kind.starts_with("ssh-") || kind.starts_with("ecdsa-") => {
+            format!("{} {}", kind, data)
+        }
+        (Some(key), _) => key.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// The comment of an SSH public key, usually `user@host`.
+pub fn key_comment(key: &str) -> Option<String> {
+    let comment = key.split_whitespace().skip(2).collect::<Vec<_>>().join(" ");
+    (!comment.is_empty()).then_some(comment)
+}
+
+/// Read a length-prefixed field of the SSH wire format.
+fn read_ssh_field<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let field = data.get(4..4 + len)?;
+    *data = &data[4 + len..];
+    Some(field)
+}
+
+/// Check that a recipient is an age key or an SSH key type age supports,
+/// and that SSH key data matches its declared type.
+pub fn validate_public_key(key: &str) -> Result<(), String> {
+    let normalized = normalize_key(key);
+    if let Some(rest) = normalized.strip_prefix("age1") {
+        if rest.len() < 55 || !rest.chars().all(|c| BECH32_CHARSET.contains(c)) {
+            return Err("Malformed age public key".to_string());
+        }
+        return Ok(());
+    }
+
+    let (kind, data) = normalized.split_once(' ')
+        .ok_or("Expected an age1… key or an SSH public key like `ssh-ed25519 AAAA…`")?;
+    if kind != "ssh-ed25519" && kind != "ssh-rsa" {
+        return Err(format!("Unsupported key type {}; age accepts ssh-ed25519 and ssh-rsa keys", kind));
+    }
+    let blob = base64::engine::general_purpose::STANDARD.decode(data)
+        .map_err(|_| format!("Malformed {} key: invalid base64", kind))?;
+    let mut rest = blob.as_slice();
+    if read_ssh_field(&mut rest) != Some(kind.as_bytes()) {
+        return Err(format!("Malformed {} key: data does not match the key type", kind));
+    }
+    Ok(())
+}
+
+/// The base64-decoded data of an SSH public key.
+fn ssh_blob(key: &str) -> Option<Vec<u8>> {
+    let normalized = normalize_key(key);
+    let (_, data) = normalized.split_once(' ')?;
+    base64::engine::general_purpose::STANDARD.decode(data).ok()
+}
+
+/// The OpenSSH `SHA256:` fingerprint of an SSH public key, as printed by
+/// `ssh-keygen -l`. Age keys have no fingerprint.
+pub fn fingerprint(key: &str) -> Option<String> {
+    let digest = Sha256::digest(ssh_blob(key)?);
+    Some(format!("SHA256:{}", base64::engine::general_purpose::STANDARD_NO_PAD.encode(digest)))
+}
+
+/// The tag age writes in an SSH recipient stanza: the first four bytes of
+/// the SHA-256 of the key data, base64 without padding.
+pub fn ssh_tag(key: &str) -> Option<String> {
+    let digest = Sha256::digest(ssh_blob(key)?);
+    Some(base64::engine::general_purpose::STANDARD_NO_PAD.encode(&digest[..4]))
+}
+
+/// Modulus size of an `ssh-rsa` key.
+pub fn rsa_bits(key: &str) -> Option<u32> {
+    let blob = ssh_blob(key)?;
+    let mut rest = blob.as_slice();
+    if read_ssh_field(&mut rest)? != b"ssh-rsa" {
+        return None;
+    }
+    let _exponent = read_ssh_field(&mut rest)?;
+    let modulus = read_ssh_field(&mut rest)?;
+    let first = modulus.iter().position(|b| *b != 0)?;
+    Some((modulus.len() - first) as u32 * 8 - modulus[first].leading_zeros())
+}
+
+/// Every distinct key in the project, as listed by `list_keys`.
+#[derive(Debug, Serialize, Clone)]
+pub struct KeyInfo {
+    /// The key without its comment.
+    pub key: String,
+    /// `age`, or the SSH key type such as `ssh-ed25519`.
+    pub kind: String,
+    pub fingerprint: Option<String>,
+    pub comment: Option<String>,
+    pub label: Option<String>,
+    /// `let` bindings in the declarations file that are exactly this key.
+    pub bindings: Vec<String>,
+    /// Group bindings that include this key.
+    pub groups: Vec<String>,
+    /// Number of secrets this key can decrypt.
+    pub secrets: usize,
+    pub bits: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct KeyInventory {
+    pub keys: Vec<KeyInfo>,
+    pub warnings: Vec<String>,
+}
+
+/// Collect every key from the evaluated declarations (secrets, groups and
+/// the `meta` argument) and from the key literals in the file itself, and
+/// flag malformed, duplicated and weak keys.
+pub fn inventory(resolved: &ResolvedAccess, literals: &[KeyLiteral], labels: &KeyLabels) -> KeyInventory {
+    let evaluated = resolved.secrets.values()
+        .chain(resolved.groups.values())
+        .chain(resolved.args.values())
+        .flatten();
+    let mut by_key: BTreeMap<String, KeyInfo> = BTreeMap::new();
+    let mut comments: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for full in evaluated.chain(literals.iter().map(|l| &l.value)) {
+        let key = normalize_key(full);
+        if key.is_empty() {
+            continue;
+        }
+        if let Some(comment) = key_comment(full) {
+            let seen = comments.entry(key.clone()).or_default();
+            if !seen.contains(&comment) {
+                seen.push(comment);
+            }
+        }
+        by_key.entry(key.clone()).or_insert_with(|| KeyInfo {
+            kind: if key.starts_with("age1") { "age".to_string() } else { key.split(' ').next().unwrap_or_default().to_string() },
+            fingerprint: fingerprint(&key),
+            comment: None,
+            label: labels.get(&key).cloned(),
+            bindings: Vec::new(),
+            groups: Vec::new(),
+            secrets: 0,
+            bits: rsa_bits(&key),
+            key,
+        });
+    }
+
+    for literal in literals {
+        if let (Some(binding), Some(info)) = (&literal.binding, by_key.get_mut(&normalize_key(&literal.value))) {
+            if !info.bindings.contains(binding) {
+                info.bindings.push(binding.clone());
+            }
+        }
+    }
+    let mut group_names: Vec<&String> = resolved.groups.keys().collect();
+    group_names.sort();
+    for group in group_names {
+        for key in &resolved.groups[group] {
+            if let Some(info) = by_key.get_mut(&normalize_key(key)) {
+                if !info.groups.contains(group) {
+                    info.groups.push(group.clone());
+                }
+            }
+        }
+    }
+    for recipients in resolved.secrets.values() {
+        let mut normalized: Vec<String> = recipients.iter().map(|k| normalize_key(k)).collect();
+        normalized.sort();
+        normalized.dedup();
+        for key in normalized {
+            if let Some(info) = by_key.get_mut(&key) {
+                info.secrets += 1;
+            }
+        }
+    }
+
+    let mut warnings = Vec::new();
+    for info in by_key.values_mut() {
+        let name = short_key(&info.key);
+        if let Err(e) = validate_public_key(&info.key) {
+            warnings.push(format!("{}: {}", name, e));
+        }
+        if let Some(bits) = info.bits.filter(|b| *b < MIN_RSA_BITS) {
+            warnings.push(format!("{}: ssh-rsa key is only {} bits; use at least {} or switch to ssh-ed25519", name, bits, MIN_RSA_BITS));
+        }
+        if info.bindings.len() > 1 {
+            warnings.push(format!("{} is defined more than once: {}", name, info.bindings.join(", ")));
+        }
+        let seen = comments.remove(&info.key).unwrap_or_default();
+        if seen.len() > 1 {
+            warnings.push(format!("{} appears with different comments: {}", name, seen.join(", ")));
+        }
+        info.comment = seen.into_iter().next();
+    }
+
+    let mut keys: Vec<KeyInfo> = by_key.into_values().collect();
+    keys.sort_by_key(|k| (k.label.clone().or_else(|| k.comment.clone()).unwrap_or_default().to_lowercase(), k.key.clone()));
+    KeyInventory { keys, warnings }
+}
+
+/// The public key for an identity file: the `.pub` file next to an SSH
+/// private key, or the `# public key:` line age-keygen writes.
+pub fn identity_public_key(identity: &Path) -> Result<String, String> {
+    let ssh_pub = PathBuf::from(format!("{}.pub", identity.display()));
+    if ssh_pub.exists() {
+        let key = fs::read_to_string(&ssh_pub)
+            .map_err(|e| format!("Failed to read {}: {}", ssh_pub.display(), e))?;
+        return Ok(key.trim().to_string());
+    }
+    let content = fs::read_to_string(identity)
+        .map_err(|e| format!("Failed to read {}: {}", identity.display(), e))?;
+    content.lines()
+        .find_map(|l| l.strip_prefix("# public key:"))
+        .map(|k| k.trim().to_string())
+        .ok_or_else(|| format!("No public key found for {}; add its .pub file", identity.display()))
+}
+
+/// A short, recognisable form of a key for display.
+pub fn short_key(key: &str) -> String {
+    let normalized = normalize_key(key);
+    let data = normalized.rsplit(' ').next().unwrap_or(&normalized);
+    if data.len() <= 16 {
+        return normalized;
+    }
+    let kind = normalized.split(' ').next().filter(|k| *k != data);
+    let tail = &data[data.len() - 8..];
+    match kind {
+        Some(kind) => format!("{} …{}", kind, tail),
+        None => format!("{}…{}", &data[..8], tail),
+    }
+}
+
+/// A human-readable name for a key: a configured label, the SSH comment, or
+/// a shortened form of the key itself.
+pub fn label_for(key: &str, labels: &KeyLabels) -> String {
+    labels.get(&normalize_key(key)).cloned()
+        .or_else(|| key_comment(key))
+        .unwrap_or_else(|| short_key(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_labels() {
+        let ssh = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAINu6Q alice@laptop";
+        let age = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+        assert_eq!(normalize_key(ssh), "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAINu6Q");
+        assert_eq!(normalize_key(age), age);
+
+        let mut labels = KeyLabels::new();
+        assert_eq!(label_for(ssh, &labels), "alice@laptop");
+        assert_eq!(label_for(age, &labels), "age1ql3z…aqmcac8p");
+        assert_eq!(label_for("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAINu6Q", &labels), "ssh-ed25519 …AAAINu6Q");
+
+        labels.insert("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAINu6Q".to_string(), "Alice".to_string());
+        assert_eq!(label_for(ssh, &labels), "Alice");
+    }
+
+    #[test]
+    fn test_validate_public_key() {
+        let ed25519 = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl alice@laptop";
+        assert!(validate_public_key(ed25519).is_ok());
+        assert!(validate_public_key("age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p").is_ok());
+        assert!(validate_public_key("age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcacbp").is_err());
+        // Type prefix says rsa, blob says ed25519
+        assert!(validate_public_key(&ed25519.replacen("ssh-ed25519", "ssh-rsa", 1)).is_err());
+        assert!(validate_public_key("ssh-ed25519 not-base64!").is_err());
+        assert!(validate_public_key("ecdsa-sha2-nistp256 AAAA").is_err());
+        assert!(validate_public_key("hello").is_err());
+    }
+
+    #[test]
+    fn test_inventory() {
+        let ed25519 = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl";
+        // 1024-bit modulus: 0x00 then 128 bytes
+        let mut blob = Vec::new();
+        for field in [&b"ssh-rsa"[..], &[1, 0, 1], &[&[0u8, 0xc0][..], &[7u8; 127]].concat()] {
+            blob.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            blob.extend_from_slice(field);
+        }
+        let rsa = format!("ssh-rsa {}", base64::engine::general_purpose::STANDARD.encode(&blob));
+        assert_eq!(rsa_bits(&rsa), Some(1024));
+        assert_eq!(fingerprint(ed25519).unwrap(), "SHA256:+DiY3wvvV6TuJJhbpZisF/zLDA0zPMSvHdkr4UvCOqU");
+
+        let mut resolved = ResolvedAccess::default();
+        resolved.secrets.insert("a.age".to_string(), vec![format!("{} alice@laptop", ed25519), ed25519.to_string()]);
+        resolved.groups.insert("tech".to_string(), vec![format!("{} alice@laptop", ed25519)]);
+        resolved.args.insert("meta".to_string(), vec![format!("{} ci", rsa)]);
+        let literals = vec![
+            KeyLiteral { value: format!("{} alice@desktop", ed25519), binding: Some("alice".to_string()) },
+            KeyLiteral { value: ed25519.to_string(), binding: Some("alice2".to_string()) },
+        ];
+        let inventory = inventory(&resolved, &literals, &KeyLabels::new());
+
+        assert_eq!(inventory.keys.len(), 2);
+        let alice = &inventory.keys[0];
+        assert_eq!(alice.comment.as_deref(), Some("alice@laptop"));
+        assert_eq!(alice.kind, "ssh-ed25519");
+        assert_eq!(alice.groups, vec!["tech"]);
+        assert_eq!(alice.bindings, vec!["alice", "alice2"]);
+        assert_eq!(alice.secrets, 1);
+        assert_eq!(inventory.keys[1].comment.as_deref(), Some("ci"));
+        assert_eq!(inventory.keys[1].secrets, 0);
+
+        assert_eq!(inventory.warnings.len(), 3);
+        assert!(inventory.warnings.iter().any(|w| w.contains("only 1024 bits")));
+        assert!(inventory.warnings.iter().any(|w| w.contains("defined more than once: alice, alice2")));
+        assert!(inventory.warnings.iter().any(|w| w.contains("different comments: alice@laptop, alice@desktop")));
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::config;
+use crate::nix_parser;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutKind {
+    /// `secrets.nix` imports a `meta_secrets.nix` holding the declarations.
+    MetaFile,
+    /// Standard agenix layout: `secrets.nix` with inline `let` bindings.
+    SecretsNix,
+    /// File names and secret root configured by the user.
+    Custom,
+}
+
+/// User-provided overrides, relative to the project directory.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LayoutOverrides {
+    pub rules_file: Option<String>,
+    pub declarations_file: Option<String>,
+    pub secret_root: Option<String>,
+}
+
+impl LayoutOverrides {
+    fn is_empty(&self) -> bool {
+        self.rules_file.is_none() && self.declarations_file.is_none() && self.secret_root.is_none()
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ProjectLayout {
+    pub kind: LayoutKind,
+    pub project_dir: PathBuf,
+    /// The rules file agenix evaluates; used to resolve recipients.
+    pub rules_file: PathBuf,
+    /// The file holding the `publicKeys` declarations we parse and edit.
+    pub declarations_file: PathBuf,
+    /// Directory that secret paths in the declarations are relative to.
+    pub secret_root: PathBuf,
+    /// Project directories of the other secret roots `discover` found below
+    /// this one; their files belong to them.
+    #[serde(skip)]
+    pub nested_roots: Vec<PathBuf>,
+}
+
+impl ProjectLayout {
+    /// Work out which files hold the rules and declarations for a project.
+    pub fn detect(project_dir: &Path, overrides: &LayoutOverrides) -> Result<Self, String> {
+        if !overrides.is_empty() {
+            let rules_file = project_dir.join(overrides.rules_file.as_deref().unwrap_or("secrets.nix"));
+            let declarations_file = overrides.declarations_file.as_deref()
+                .map(|f| project_dir.join(f))
+                .unwrap_or_else(|| rules_file.clone());
+            let secret_root = overrides.secret_root.as_deref()
+                .map(|d| project_dir.join(d))
+                .unwrap_or_else(|| rules_file.parent().unwrap_or(project_dir).to_path_buf());
+            for file in [&rules_file, &declarations_file] {
+                if !file.exists() {
+                    return Err(format!("{} not found", file.display()));
+                }
+            }
+            if !secret_root.is_dir() {
+                return Err(format!("Secret root {} is not a directory", secret_root.display()));
+            }
+            return Ok(ProjectLayout {
+                kind: LayoutKind::Custom,
+                project_dir: project_dir.to_path_buf(),
+                rules_file,
+                declarations_file,
+                secret_root,
+                nested_roots: Vec::new(),
+            });
+        }
+
+        let meta = project_dir.join("meta_secrets.nix");
+        let rules = project_dir.join("secrets.nix");
+        if meta.exists() {
+            return Ok(ProjectLayout {
+                kind: LayoutKind::MetaFile,
+                project_dir: project_dir.to_path_buf(),
+                rules_file: rules,
+                declarations_file: meta,
+                secret_root: project_dir.to_path_buf(),
+                nested_roots: Vec::new(),
+            });
+        }
+
+        // Plain agenix layout, either at the top level or in a `secrets/` folder
+        for root in [project_dir.to_path_buf(), project_dir.join("secrets")] {
+            let rules = root.join("secrets.nix");
+            if rules.exists() {
+                return Ok(ProjectLayout {
+                    kind: LayoutKind::SecretsNix,
+                    project_dir: project_dir.to_path_buf(),
+                    rules_file: rules.clone(),
+                    declarations_file: rules,
+                    secret_root: root,
+                    nested_roots: Vec::new(),
+                });
+            }
+        }
+
+        Err(format!("No meta_secrets.nix or secrets.nix found in {}", project_dir.display()))
+    }
+
+    /// File name of the declarations file, for messages.
+    pub fn declarations_name(&self) -> String {
+        self.declarations_file.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.declarations_file.display().to_string())
+    }
+
+    /// Every `.age` file below the secret root, relative to it and sorted.
+    /// Files inside a nested secret root belong to that project and are left
+    /// out.
+    pub fn age_files(&self) -> Result<Vec<String>, String> {
+        let nested = self.nested_roots.clone();
+        let mut files: Vec<String> = walk(&self.secret_root, move |dir| !nested.iter().any(|r| r == dir))
+            .filter(|path| path.extension().is_some_and(|e| e == "age"))
+            .filter_map(|path| {
+                path.strip_prefix(&self.secret_root).ok()
+                    .map(|p| p.to_string_lossy().to_string())
+            })
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    pub fn read_declarations(&self) -> Result<String, String> {
+        fs::read_to_string(&self.declarations_file)
+            .map_err(|e| format!("Failed to read {}: {}", self.declarations_name(), e))
+    }
+
+    pub fn write_declarations(&self, content: &str) -> Result<(), String> {
+        fs::write(&self.declarations_file, content)
+            .map_err(|e| format!("Failed to write {}: {}", self.declarations_name(), e))
+    }
+}
+
+const ROOT_FILES: &[&str] = &["meta_secrets.nix", "secrets.nix"];
+
+/// Directories never scanned. `.thoughtseize` holds the trash, whose
+/// ciphertexts are no longer secrets; build output is left to `.gitignore`.
+const SKIPPED_DIRS: &[&str] = &[".git", ".thoughtseize"];
+
+/// Files below `dir`, honouring `.gitignore`. Hidden entries are included, as
+/// secrets like `app/.env.age` are common. Symlinks are not followed, so a
+/// `result` link into the Nix store is never entered. Subdirectories for which
+/// `descend` is false are pruned.
+pub fn walk(dir: &Path, descend: impl Fn(&Path) -> bool + Send + Sync + 'static) -> impl Iterator<Item = PathBuf> {
+    ignore::WalkBuilder::new(dir)
+        .hidden(false)
+        .follow_links(false)
+        .require_git(false)
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            !is_dir || entry.depth() == 0 || (
+                !SKIPPED_DIRS.contains(&entry.file_name().to_string_lossy().as_ref()) && descend(entry.path())
+            )
+        })
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| entry.into_path())
+}
+
+/// Whether a nested root candidate holds agenix rules rather than, say, a
+/// NixOS module that happens to be called `secrets.nix`: it declares an
+/// `.age` file or a public key.
+fn holds_rules(layout: &ProjectLayout) -> bool {
+    if layout.kind == LayoutKind::MetaFile {
+        return true;
+    }
+    let Ok(content) = layout.read_declarations() else { return false };
+    nix_parser::parse_meta_secrets(&content).is_ok_and(|p| p.secrets.iter().any(|s| s.path.ends_with(".age")))
+        || !nix_parser::key_literals(&content).is_empty()
+}
+
+/// Every project below `dir`, the directory itself first. Each directory
+/// holding a `meta_secrets.nix` or `secrets.nix` that `ProjectLayout::detect`
+/// accepts is its own project, and is left out of the scans of the roots
+/// above it. With overrides only `dir` itself is opened.
+pub fn discover(dir: &Path, overrides: &LayoutOverrides) -> Result<Vec<ProjectLayout>, String> {
+    if !overrides.is_empty() {
+        return ProjectLayout::detect(dir, overrides).map(|l| vec![l]);
+    }
+    let top = ProjectLayout::detect(dir, overrides);
+    let mut roots: Vec<ProjectLayout> = top.iter().cloned().collect();
+
+    let mut nested: Vec<PathBuf> = walk(dir, |_| true)
+        .filter(|f| f.file_name().is_some_and(|n| ROOT_FILES.iter().any(|r| n == *r)))
+        .filter_map(|f| f.parent().map(Path::to_path_buf))
+        .collect();
+    nested.sort();
+    nested.dedup();
+    for candidate in nested {
+        if let Some(layout) = ProjectLayout::detect(&candidate, &LayoutOverrides::default()).ok().filter(holds_rules) {
+            if !roots.iter().any(|r| r.declarations_file == layout.declarations_file) {
+                roots.push(layout);
+            }
+        }
+    }
+
+    let dirs: Vec<PathBuf> = roots.iter().map(|r| r.project_dir.clone()).collect();
+    for root in &mut roots {
+        root.nested_roots = dirs.iter()
+            .filter(|d| **d != root.project_dir && d.starts_with(&root.secret_root))
+            .cloned()
+            .collect();
+    }
+
+    match top {
+        Err(e) if roots.is_empty() => Err(e),
+        _ => Ok(roots),
+    }
+}
+
+/// Create the rules and declarations files for a new project of `kind`,
+/// with one group holding `key`. Existing files are never overwritten.
+pub fn scaffold(project_dir: &Path, kind: LayoutKind, group: &str, key: &str) -> Result<(), String> {
+    let declarations = format!(
+        "let\n  {} = [\n    \"{}\"\n  ];\nin\n{{\n}}\n",
+        group,
+        key.replace('\\', "\\\\").replace('"', "\\\"").replace("${", "\\${")
+    );
+    let files: Vec<(&str, String)> = match kind {
+        LayoutKind::MetaFile => vec![
+            ("meta_secrets.nix", format!("{{ meta ? {{ }} }}:\n{}", declarations)),
+            ("secrets.nix", "import ./meta_secrets.nix { }\n".to_string()),
+        ],
+        LayoutKind::SecretsNix => vec![("secrets.nix", declarations)],
+        LayoutKind::Custom => return Err("Choose the meta file or secrets.nix layout for a new project".to_string()),
+    };
+    for (name, _) in &files {
+        let path = project_dir.join(name);
+        if path.exists() {
+            return Err(format!("{} already exists", path.display()));
+        }
+    }
+    fs::create_dir_all(project_dir)
+        .map_err(|e| format!("Failed to create {}: {}", project_dir.display(), e))?;
+    for (name, content) in files {
+        let path = project_dir.join(name);
+        fs::write(&path, content)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Load the layout overrides saved for a project directory.
+pub fn load_overrides(project_dir: &str) -> LayoutOverrides {
+    config::load_config_entry::<HashMap<String, LayoutOverrides>>("layouts")
+        .and_then(|mut layouts| layouts.remove(project_dir))
+        .unwrap_or_default()
+}
+
+/// Remember layout overrides for a project directory. Empty overrides clear
+/// the saved entry so the layout is detected again.
+pub fn save_overrides(project_dir: &str, overrides: &LayoutOverrides) {
+    let mut layouts = config::load_config_entry::<HashMap<String, LayoutOverrides>>("layouts")
+        .unwrap_or_default();
+    if overrides.is_empty() {
+        layouts.remove(project_dir);
+    } else {
+        layouts.insert(project_dir.to_string(), overrides.clone());
+    }
+    config::save_config_entry("layouts", &layouts);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scaffold() {
+        let key = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAINu6Q alice@laptop";
+        for kind in [LayoutKind::MetaFile, LayoutKind::SecretsNix] {
+            let dir = std::env::temp_dir().join(format!("thoughtseize-scaffold-{:?}-{}", kind, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            scaffold(&dir, kind, "admins", key).unwrap();
+
+            let layout = ProjectLayout::detect(&dir, &LayoutOverrides::default()).unwrap();
+            assert_eq!(layout.kind, kind);
+            let parsed = crate::nix_parser::parse_meta_secrets(&layout.read_declarations().unwrap()).unwrap();
+            assert_eq!(parsed.groups[0].name, "admins");
+            assert!(parsed.groups[0].definition.contains(key));
+            assert!(parsed.secrets.is_empty());
+            assert!(scaffold(&dir, kind, "admins", key).is_err());
+            let _ = fs::remove_dir_all(&dir);
+        }
+    }
+
+    #[test]
+    fn test_discover_nested_roots() {
+        let dir = std::env::temp_dir().join(format!("thoughtseize-discover-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let key = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAINu6Q";
+        scaffold(&dir, LayoutKind::MetaFile, "admins", key).unwrap();
+        scaffold(&dir.join("team/api"), LayoutKind::SecretsNix, "admins", key).unwrap();
+        scaffold(&dir.join("node_modules/pkg"), LayoutKind::SecretsNix, "admins", key).unwrap();
+        // A host module that happens to be called secrets.nix is not a root
+        fs::create_dir_all(dir.join("hosts/web")).unwrap();
+        fs::write(dir.join("hosts/web/secrets.nix"), "{ age.secrets.db.file = ../../team/api/db.age; }\n").unwrap();
+        for file in ["top.age", "team/other.age", "team/.env.age", "team/api/db.age", "hosts/web/web.age", "build/x.age", ".thoughtseize/trash/t/old.age", "target/kept.age"] {
+            fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            fs::write(dir.join(file), "").unwrap();
+        }
+        fs::write(dir.join(".gitignore"), "build/\nnode_modules/\n").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("team"), dir.join("linked")).unwrap();
+
+        let roots = discover(&dir, &LayoutOverrides::default()).unwrap();
+        let dirs: Vec<&Path> = roots.iter().map(|r| r.project_dir.as_path()).collect();
+        assert_eq!(dirs, vec![dir.as_path(), dir.join("team/api").as_path()]);
+
+        let mut top = roots[0].age_files().unwrap();
+        top.sort();
+        // Only `.gitignore` keeps build output out; `target/` is not ignored here
+        assert_eq!(top, vec!["hosts/web/web.age", "target/kept.age", "team/.env.age", "team/other.age", "top.age"]);
+        assert_eq!(roots[1].age_files().unwrap(), vec!["db.age"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
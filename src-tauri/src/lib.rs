@@ -1,8 +1,25 @@
+pub mod access;
 pub mod age_cli;
+pub mod cli;
 pub mod commands;
 pub mod config;
+pub mod consumers;
+pub mod formats;
+pub mod hosts;
+pub mod keys;
+pub mod layout;
+pub mod metadata;
+pub mod nix_eval;
 pub mod nix_parser;
+pub mod nixos;
+pub mod plan;
+pub mod process;
+pub mod rotation;
 pub mod state;
+pub mod status;
+pub mod tasks;
+pub mod trash;
+pub mod tree;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -13,10 +30,44 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             commands::project::open_project,
             commands::project::get_saved_project,
+            commands::project::init_project,
+            commands::project::select_root,
+            commands::project::check_recipients,
             commands::secrets::decrypt_secret,
             commands::secrets::save_secret,
+            commands::secrets::decrypt_secret_fields,
+            commands::secrets::save_secret_fields,
             commands::secrets::create_secret,
             commands::secrets::delete_secret,
+            commands::secrets::rename_secret,
+            commands::secrets::get_secret_metadata,
+            commands::secrets::update_secret_metadata,
+            commands::secrets::mark_secret_rotated,
+            commands::trash::list_trash,
+            commands::trash::restore_secret,
+            commands::trash::empty_trash,
+            commands::bulk::import_secrets,
+            commands::bulk::export_secrets,
+            commands::plans::apply_plan,
+            commands::plans::discard_plan,
+            commands::rotation::rotation_report,
+            commands::access::access_matrix,
+            commands::access::export_access_matrix,
+            commands::access::set_key_label,
+            commands::groups::list_groups,
+            commands::groups::meta_entries,
+            commands::groups::update_group,
+            commands::keys::list_keys,
+            commands::keys::offboard_key,
+            commands::keys::onboard_key,
+            commands::consumers::find_consumers,
+            commands::consumers::check_hosts,
+            commands::nixos::age_secret_declaration,
+            commands::tasks::cancel_task,
+            commands::settings::get_timeouts,
+            commands::settings::set_timeouts,
+            commands::settings::get_age_secret_defaults,
+            commands::settings::set_age_secret_defaults,
             commands::identity::list_identities,
             commands::identity::get_saved_identity,
             commands::identity::set_identity,
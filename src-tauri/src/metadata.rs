@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::layout::ProjectLayout;
+
+/// Descriptive information about a secret that agenix itself has no place for.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SecretMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotated: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation_interval_days: Option<u32>,
+}
+
+impl SecretMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == SecretMetadata::default()
+    }
+}
+
+/// Contents of the metadata file, keyed by secret path. A `BTreeMap` keeps
+/// the file sorted so diffs stay small when it is committed to git.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct MetadataFile {
+    #[serde(default)]
+    pub secrets: BTreeMap<String, SecretMetadata>,
+}
+
+impl MetadataFile {
+    pub fn get(&self, path: &str) -> Option<&SecretMetadata> {
+        self.secrets.get(path)
+    }
+
+    /// Replace a secret's metadata; empty metadata removes the entry.
+    pub fn set(&mut self, path: &str, metadata: SecretMetadata) {
+        if metadata.is_empty() {
+            self.secrets.remove(path);
+        } else {
+            self.secrets.insert(path.to_string(), metadata);
+        }
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<SecretMetadata> {
+        self.secrets.remove(path)
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if let Some(metadata) = self.secrets.remove(from) {
+            self.secrets.insert(to.to_string(), metadata);
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default() + "\n"
+    }
+}
+
+/// The metadata file is meant to be versioned alongside the declarations.
+pub fn metadata_path(layout: &ProjectLayout) -> PathBuf {
+    layout.project_dir.join(".thoughtseize").join("metadata.json")
+}
+
+/// Load the project's metadata; a missing file means no metadata yet.
+pub fn load(layout: &ProjectLayout) -> Result<MetadataFile, String> {
+    let path = metadata_path(layout);
+    if !path.exists() {
+        return Ok(MetadataFile::default());
+    }
+    let json = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&json)
+        .map_err(|e| format!("Invalid metadata file {}: {}", path.display(), e))
+}
+
+pub fn save(layout: &ProjectLayout, file: &MetadataFile) -> Result<(), String> {
+    let path = metadata_path(layout);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(&path, file.to_json())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_roundtrip_and_rename() {
+        let mut file = MetadataFile::default();
+        file.set("b.age", SecretMetadata {
+            owner: Some("ops".to_string()),
+            rotation_interval_days: Some(90),
+            rotated: Some("2025-01-02T03:04:05Z".parse().unwrap()),
+            ..Default::default()
+        });
+        file.set("a.age", SecretMetadata { description: Some("API key".to_string()), ..Default::default() });
+
+        let json = file.to_json();
+        assert!(json.find("\"a.age\"").unwrap() < json.find("\"b.age\"").unwrap());
+        assert!(json.contains("\"rotated\": \"2025-01-02T03:04:05Z\""));
+        assert!(!json.contains("tags"));
+        assert_eq!(serde_json::from_str::<MetadataFile>(&json).unwrap(), file);
+
+        file.rename("b.age", "c.age");
+        assert!(file.get("b.age").is_none());
+        assert_eq!(file.get("c.age").unwrap().owner.as_deref(), Some("ops"));
+
+        file.set("a.age", SecretMetadata::default());
+        assert!(file.get("a.age").is_none());
+    }
+}
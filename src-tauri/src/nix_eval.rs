@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::layout::ProjectLayout;
+
+/// Resolve recipients for a secret by reading its publicKeys from secrets.nix via nix eval.
+/// This imports secrets.nix (which resolves all group definitions) and extracts the
+/// publicKeys attribute for the given secret path.
+pub fn resolve_recipients(layout: &ProjectLayout, secret_path: &str) -> Result<Vec<String>, String> {
+    validate_secret_path(secret_path)?;
+
+    // Import secrets.nix which fully resolves all group definitions,
+    // then index into the resulting attrset by the secret's path to get publicKeys.
+    let json_str = nix_eval_json(layout, &format!(
+        "(import {}).\"{}\".publicKeys",
+        layout.rules_file.display(),
+        secret_path
+    ))?;
+
+    let keys: Vec<String> = serde_json::from_str(&json_str)
+        .map_err(|e| format!("Failed to parse nix eval output: {}", e))?;
+
+    Ok(keys)
+}
+
+/// Resolve recipients for many secrets with a single nix eval.
+pub fn resolve_recipients_batch(
+    layout: &ProjectLayout,
+    secret_paths: &[String],
+) -> Result<HashMap<String, Vec<String>>, String> {
+    for path in secret_paths {
+        validate_secret_path(path)?;
+    }
+
+    let list = secret_paths.iter()
+        .map(|p| format!("\"{}\"", p))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let json_str = nix_eval_json(layout, &format!(
+        "let s = import {}; in builtins.listToAttrs (map (p: {{ name = p; value = s.${{p}}.publicKeys; }}) [ {} ])",
+        layout.rules_file.display(),
+        list
+    ))?;
+
+    serde_json::from_str(&json_str)
+        .map_err(|e| format!("Failed to parse nix eval output: {}", e))
+}
+
+/// Prefixes of the synthetic entries used to evaluate group bindings,
+/// function arguments such as `meta`, and selections into them.
+const GROUP_PREFIX: &str = "__thoughtseize_group__/";
+const ARG_PREFIX: &str = "__thoughtseize_arg__/";
+const REF_PREFIX: &str = "__thoughtseize_ref__/";
+
+/// Nix function collecting every key-like string in a value, a few levels
+/// deep. Values that fail to evaluate and derivations are skipped.
+const COLLECT_KEYS: &str = "(let collect = d: v: let r = builtins.tryEval v; x = r.value; in \
+    if !r.success then [ ] \
+    else if builtins.isString x then (if builtins.match \"(ssh-|ecdsa-|sk-|age1).*\" x != null then [ x ] else [ ]) \
+    else if d == 0 then [ ] \
+    else if builtins.isList x then builtins.concatMap (collect (d - 1)) x \
+    else if builtins.isAttrs x && (x.type or null) != \"derivation\" then builtins.concatMap (collect (d - 1)) (builtins.attrValues x) \
+    else [ ]; in collect 6)";
+
+/// Concrete keys behind every secret and every group binding.
+#[derive(Debug, Default)]
+pub struct ResolvedAccess {
+    pub secrets: HashMap<String, Vec<String>>,
+    pub groups: HashMap<String, Vec<String>>,
+    /// Keys found in the declarations file's function arguments (`meta`).
+    pub args: HashMap<String, Vec<String>>,
+    /// Keys behind attribute selections used in group definitions, such as
+    /// `meta.ssh.groups.TECH`.
+    pub references: HashMap<String, Vec<String>>,
+}
+
+/// A Nix string literal for `path`.
+fn nix_string(path: &std::path::Path) -> String {
+    format!("\"{}\"", path.display().to_string().replace('\\', "\\\\").replace('"', "\\\"").replace("${", "\\${"))
+}
+
+/// A Nix expression for an absolute path, safe for paths containing spaces.
+fn nix_path(path: &std::path::Path) -> String {
+    format!("(/. + {})", nix_string(path))
+}
+
+/// Evaluate the rules with `declarations` standing in for the declarations
+/// file, resolving every secret and group binding to its keys. Groups,
+/// function arguments and the selections group definitions make into them
+/// are evaluated by appending one synthetic secret per name, holding the
+/// key-like strings found in its value.
+pub fn evaluate_declarations(layout: &ProjectLayout, declarations: &str) -> Result<ResolvedAccess, String> {
+    let parsed = crate::nix_parser::parse_meta_secrets(declarations)?;
+    let args = crate::nix_parser::function_args(declarations);
+    let group_names: Vec<&str> = parsed.groups.iter().map(|g| g.name.as_str()).collect();
+    // Only selections rooted at a binding or argument are in scope for a probe
+    let mut references: Vec<String> = parsed.groups.iter()
+        .flat_map(|g| crate::nix_parser::references(&g.definition))
+        .filter(|r| r.contains('.'))
+        .filter(|r| r.split('.').next().is_some_and(|root| group_names.contains(&root) || args.iter().any(|a| a == root)))
+        .collect();
+    references.sort();
+    references.dedup();
+    let names = group_names.iter()
+        .map(|g| (GROUP_PREFIX, g.to_string()))
+        .chain(args.iter().map(|a| (ARG_PREFIX, a.clone())))
+        .chain(references.into_iter().map(|r| (REF_PREFIX, r)));
+    let probes: String = names
+        .filter(|(_, name)| crate::nix_parser::is_group_reference(name))
+        .map(|(prefix, name)| format!("  \"{}{}\".publicKeys = {} {};\n", prefix, name, COLLECT_KEYS, name))
+        .collect();
+    let content = crate::nix_parser::append_statements(declarations, &probes);
+    let result = eval_with_declarations(layout, &content, "builtins.mapAttrs (n: v: v.publicKeys or [ ])");
+
+    let all: HashMap<String, Vec<String>> = serde_json::from_str(&result?)
+        .map_err(|e| format!("Failed to parse nix eval output: {}", e))?;
+    let mut access = ResolvedAccess::default();
+    for (name, keys) in all {
+        if let Some(group) = name.strip_prefix(GROUP_PREFIX) {
+            if !keys.is_empty() {
+                access.groups.insert(group.to_string(), keys);
+            }
+        } else if let Some(arg) = name.strip_prefix(ARG_PREFIX) {
+            access.args.insert(arg.to_string(), keys);
+        } else if let Some(reference) = name.strip_prefix(REF_PREFIX) {
+            access.references.insert(reference.to_string(), keys);
+        } else {
+            access.secrets.insert(name, keys);
+        }
+    }
+    Ok(access)
+}
+
+/// Name prefix of the temporary declarations written for an evaluation.
+pub(crate) const EVAL_FILE_PREFIX: &str = ".thoughtseize-eval-";
+
+/// Removes the temporary declarations once evaluation is over, including when
+/// `nix` fails or is killed on timeout.
+struct TempFile(std::path::PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Evaluate `select` applied to the rules, with `content` standing in for the
+/// declarations file.
+fn eval_with_declarations(layout: &ProjectLayout, content: &str, select: &str) -> Result<String, String> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    // Written next to the original so relative imports keep working; named
+    // per call, as commands evaluate concurrently
+    let dir = layout.declarations_file.parent().unwrap_or(&layout.project_dir);
+    let temp = TempFile(dir.join(format!(
+        "{}{}-{}.nix",
+        EVAL_FILE_PREFIX,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )));
+    std::fs::write(&temp.0, content)
+        .map_err(|e| format!("Failed to write {}: {}", temp.0.display(), e))?;
+
+    let rules = if layout.rules_file == layout.declarations_file {
+        format!("import {}", nix_path(&temp.0))
+    } else {
+        format!(
+            "builtins.scopedImport {{ import = p: if toString p == toString {} then import {} else import p; }} {}",
+            nix_path(&layout.declarations_file),
+            nix_path(&temp.0),
+            nix_path(&layout.rules_file)
+        )
+    };
+    nix_eval_json(layout, &format!("{} ({})", select, rules))
+}
+
+const META_PROBE: &str = "__thoughtseize_meta__";
+
+/// An attribute inside a function argument of the declarations file, such as
+/// `meta.ssh.groups.TECH`, with the keys found below it.
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct MetaEntry {
+    /// A Nix expression selecting the attribute.
+    pub path: String,
+    /// What to write in `publicKeys` for it: `path`, or `[ path ]` when the
+    /// attribute is a single key rather than a list.
+    pub expression: String,
+    pub keys: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct RawMetaEntry {
+    path: Vec<String>,
+    single: bool,
+    keys: Vec<String>,
+}
+
+/// Walk the attrsets passed to the declarations file (`{ meta }:`) and list
+/// every attribute that holds keys, a few levels deep.
+pub fn meta_entries(layout: &ProjectLayout, declarations: &str) -> Result<Vec<MetaEntry>, String> {
+    let args: Vec<String> = crate::nix_parser::function_args(declarations).into_iter()
+        .filter(|a| crate::nix_parser::is_identifier(a))
+        .collect();
+    if args.is_empty() {
+        return Ok(Vec::new());
+    }
+    let roots: String = args.iter().map(|a| format!("(walk 4 [ \"{}\" ] {})", a, a)).collect::<Vec<_>>().join(" ++ ");
+    let probe = format!(
+        "  \"{}\".publicKeys = let collect = {}; \
+    walk = d: prefix: v: let r = builtins.tryEval v; x = r.value; in \
+      if !r.success || d == 0 || !(builtins.isAttrs x) || (x.type or null) == \"derivation\" then [ ] \
+      else builtins.concatLists (builtins.attrValues (builtins.mapAttrs (n: c: \
+        let p = prefix ++ [ n ]; keys = collect c; in \
+        if keys == [ ] then [ ] else [ {{ path = p; single = builtins.isString c; inherit keys; }} ] ++ walk (d - 1) p c) x)); \
+    in {};\n",
+        META_PROBE, COLLECT_KEYS, roots
+    );
+    let content = crate::nix_parser::append_statements(declarations, &probe);
+    let json = eval_with_declarations(layout, &content, &format!("(x: x.\"{}\".publicKeys)", META_PROBE))?;
+    let raw: Vec<RawMetaEntry> = serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse nix eval output: {}", e))?;
+
+    let mut entries: Vec<MetaEntry> = raw.into_iter()
+        .map(|e| {
+            let path = attr_path(&e.path);
+            let expression = if e.single { format!("[ {} ]", path) } else { path.clone() };
+            MetaEntry { path, expression, keys: e.keys }
+        })
+        .collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Join attribute names into a selection, quoting names that need it.
+fn attr_path(names: &[String]) -> String {
+    names.iter()
+        .map(|n| if crate::nix_parser::is_identifier(n) {
+            n.clone()
+        } else {
+            format!("\"{}\"", n.replace('\\', "\\\\").replace('"', "\\\"").replace("${", "\\${"))
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Allowlist: only permit safe path characters (alphanumeric, ., _, /, -)
+pub fn validate_secret_path(secret_path: &str) -> Result<(), String> {
+    if !secret_path.chars().all(|c| c.is_alphanumeric() || "._/-".contains(c)) {
+        return Err("Invalid characters in secret path".to_string());
+    }
+    Ok(())
+}
+
+/// What a NixOS configuration in the local flake says about its secrets.
+#[derive(Debug, serde::Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct HostConfig {
+    pub host_name: String,
+    /// `age.secrets.<name>.file` as a string; `None` if it failed to evaluate.
+    pub secrets: Option<HashMap<String, String>>,
+    /// Public keys in `programs.ssh.knownHosts` naming this host.
+    pub known_host_keys: Vec<String>,
+}
+
+/// Evaluate `nixosConfigurations` of the flake at `flake_dir` without
+/// network access, so only inputs already in the local store are used.
+/// Hosts whose options fail to evaluate come back with `secrets: None`.
+pub fn evaluate_hosts(flake_dir: &std::path::Path) -> Result<HashMap<String, HostConfig>, String> {
+    if !flake_dir.join("flake.nix").exists() {
+        return Err(format!("No flake.nix in {}", flake_dir.display()));
+    }
+    let expr = format!(
+        "let flake = builtins.getFlake {}; \
+           safe = v: d: let r = builtins.tryEval (builtins.deepSeq v v); in if r.success then r.value else d; \
+         in builtins.mapAttrs (name: host: let \
+           c = host.config; \
+           hostName = safe c.networking.hostName name; \
+         in {{ \
+           inherit hostName; \
+           secrets = safe (builtins.mapAttrs (n: s: toString s.file) (c.age.secrets or {{ }})) null; \
+           knownHostKeys = safe (builtins.concatLists (builtins.attrValues (builtins.mapAttrs (n: k: \
+             if builtins.elem hostName ([ n ] ++ (k.hostNames or [ ])) && (k.publicKey or null) != null then [ k.publicKey ] else [ ]) \
+             (c.programs.ssh.knownHosts or {{ }})))) [ ]; \
+         }}) (flake.nixosConfigurations or {{ }})",
+        nix_string(flake_dir)
+    );
+    let mut cmd = std::process::Command::new("nix");
+    cmd.args(["eval", "--offline", "--impure", "--json", "--extra-experimental-features", "nix-command flakes", "--expr"])
+        .arg(expr)
+        .current_dir(flake_dir);
+    let json = run_nix(&mut cmd)?;
+    serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse nix eval output: {}", e))
+}
+
+pub fn nix_eval_json(layout: &ProjectLayout, expr: &str) -> Result<String, String> {
+    if !layout.rules_file.exists() {
+        return Err(format!("{} not found", layout.rules_file.display()));
+    }
+    let eval_dir = layout.rules_file.parent().unwrap_or(&layout.project_dir);
+
+    let mut cmd = std::process::Command::new("nix");
+    cmd.args(["eval", "--impure", "--json", "--expr"])
+        .arg(expr)
+        .current_dir(eval_dir);
+    run_nix(&mut cmd)
+}
+
+fn run_nix(cmd: &mut std::process::Command) -> Result<String, String> {
+    let output = crate::process::run(cmd, None, crate::process::timeouts().nix())?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("nix eval failed: {}", stderr));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_secret_path() {
+        assert!(validate_secret_path("web/db-password.age").is_ok());
+        assert!(validate_secret_path("app/.env.age").is_ok());
+        assert!(validate_secret_path("db\".age").is_err());
+        assert!(validate_secret_path("${builtins.abort \"x\"}.age").is_err());
+        assert!(validate_secret_path("a b.age").is_err());
+    }
+
+    #[test]
+    fn test_attr_path() {
+        let names: Vec<String> = ["meta", "ssh", "nodes", "10.0.0.1", "a\"b", "${x}"].iter().map(|s| s.to_string()).collect();
+        let path = attr_path(&names);
+        assert_eq!(path, "meta.ssh.nodes.\"10.0.0.1\".\"a\\\"b\".\"\\${x}\"");
+        assert!(crate::nix_parser::is_group_reference(&path));
+    }
+}
@@ -6,11 +6,24 @@ pub struct GroupDef {
     pub definition: String,
 }
 
+#[derive(Debug, Serialize, Clone)]
+pub struct SecretAttr {
+    pub name: String,
+    pub expr: String,
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct SecretEntry {
     pub path: String,
     pub groups: Vec<String>,
     pub raw_expr: String,
+    /// Whether agenix should produce ASCII-armored output (`armor = true;`).
+    pub armor: bool,
+    /// Every attribute declared for the secret, including `publicKeys`.
+    pub attrs: Vec<SecretAttr>,
+    /// Byte ranges of the statements declaring this secret in the parsed source.
+    #[serde(skip)]
+    pub spans: Vec<(usize, usize)>,
 }
 
 #[derive(Debug, Serialize)]
@@ -19,30 +32,51 @@ pub struct ParsedSecrets {
     pub secrets: Vec<SecretEntry>,
 }
 
-pub fn parse_meta_secrets(content: &str) -> Result<ParsedSecrets, String> {
-    let mut groups = Vec::new();
-    let mut secrets = Vec::new();
+/// Find the byte offset of the first line starting with `keyword` (followed
+/// by whitespace or a brace), so `let` at the very top of a file is found too.
+fn find_keyword_line(content: &str, keyword: &str) -> Option<usize> {
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let rest = line.strip_prefix(keyword);
+        if let Some(rest) = rest {
+            if rest.is_empty() || rest.starts_with(|c: char| c.is_whitespace() || c == '{') {
+                return Some(offset);
+            }
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Split a publicKeys expression into the names it references. List literals
+/// of plain identifiers (`[ user1 system1 ]`, common in standard agenix files)
+/// are expanded into their elements.
+fn expr_group_names(expr: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for part in expr.split("++").map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let elements: Option<Vec<&str>> = part.strip_prefix('[')
+            .and_then(|p| p.strip_suffix(']'))
+            .map(|inner| inner.split_whitespace().collect());
+        match elements {
+            Some(elements) if elements.iter().all(|e| e.chars().all(|c| c.is_alphanumeric() || "_-'".contains(c))) => {
+                names.extend(elements.into_iter().map(|e| e.to_string()));
+            }
+            _ => names.push(part.to_string()),
+        }
+    }
+    names
+}
 
-    // Find the let..in block to extract group definitions
-    let let_start = content.find("\nlet\n")
-        .or_else(|| content.find("\nlet "))
-        .map(|i| i + 1)
-        .ok_or("No 'let' block found")?;
-    let in_start = content.find("\nin\n")
-        .or_else(|| content.find("\nin "))
-        .or_else(|| content.find("\nin{"))
-        .map(|i| i + 1)
-        .ok_or("No 'in' block found")?;
-
-    let let_block = &content[let_start..in_start];
-
-    // Parse group definitions: "name = expr;" (may span multiple lines)
-    let let_text = let_block.to_string();
+/// Parse group definitions in a `let` block: "name = expr;" (may span multiple lines)
+fn parse_let_block(let_block: &str, groups: &mut Vec<GroupDef>) {
     let mut i = 0;
-    let lines: Vec<&str> = let_text.lines().collect();
+    let lines: Vec<&str> = let_block.lines().collect();
     while i < lines.len() {
-        let line = lines[i].trim();
-        if line.starts_with("let") || line.is_empty() {
+        let mut line = lines[i].trim();
+        if let Some(rest) = line.strip_prefix("let") {
+            line = rest.trim_start();
+        }
+        if line.is_empty() || line.starts_with('#') {
             i += 1;
             continue;
         }
@@ -61,66 +95,321 @@ pub fn parse_meta_secrets(content: &str) -> Result<ParsedSecrets, String> {
         }
         i += 1;
     }
+}
+
+pub fn parse_meta_secrets(content: &str) -> Result<ParsedSecrets, String> {
+    let mut groups = Vec::new();
+    let mut secrets = Vec::new();
+
+    // Find the let..in block to extract group definitions. Files without
+    // bindings are a bare (possibly function-wrapped) attrset.
+    let in_start = match find_keyword_line(content, "let") {
+        Some(let_start) => {
+            let in_start = find_keyword_line(&content[let_start..], "in")
+                .map(|i| let_start + i)
+                .ok_or("No 'in' block found")?;
+            let let_block = &content[let_start..in_start];
+            parse_let_block(let_block, &mut groups);
+            in_start
+        }
+        None => {
+            // Skip a `{ meta }:` style argument set
+            let trimmed = content.trim_start();
+            let offset = content.len() - trimmed.len();
+            match trimmed.find("}:").filter(|_| trimmed.starts_with('{')) {
+                Some(end) => offset + end + 2,
+                None => 0,
+            }
+        }
+    };
 
     // Parse secret entries from the attrset body (after "in\n{")
     let body_start = content[in_start..].find('{')
         .map(|i2| in_start + i2 + 1)
         .ok_or("No '{' after 'in'")?;
     let body_end = content.rfind('}').ok_or("No closing '}'")?;
-    let body = &content[body_start..body_end];
+    if body_end < body_start {
+        return Err("No closing '}'".to_string());
+    }
 
-    // Match: "path.age".publicKeys = expr;
-    let mut current_line = String::new();
-    for line in body.lines() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with('#') {
+    // Statements are either `"path".attr = expr;` or `"path" = { attr = expr; ... };`
+    for (start, end) in split_statements(&content[body_start..body_end]) {
+        let (start, end) = (body_start + start, body_start + end);
+        let Some((lhs, rhs)) = split_binding(&content[start..end - 1]) else {
+            continue;
+        };
+        let attr_path = parse_attr_path(lhs);
+        let Some(path) = attr_path.first() else {
             continue;
+        };
+
+        let mut attrs = Vec::new();
+        match attr_path.len() {
+            1 => {
+                let rhs = rhs.trim();
+                let Some(inner) = rhs.strip_prefix('{').and_then(|r| r.strip_suffix('}')) else {
+                    continue;
+                };
+                for (s2, e2) in split_statements(inner) {
+                    if let Some((name, expr)) = split_binding(&inner[s2..e2 - 1]) {
+                        attrs.push(SecretAttr { name: name.trim().to_string(), expr: normalize_expr(expr) });
+                    }
+                }
+            }
+            2 => attrs.push(SecretAttr { name: attr_path[1].clone(), expr: normalize_expr(rhs) }),
+            _ => continue,
         }
-        if current_line.is_empty() {
-            current_line.push_str(trimmed);
-        } else {
-            current_line.push(' ');
-            current_line.push_str(trimmed);
+
+        let span = line_span(content, start, end);
+        match secrets.iter_mut().find(|s: &&mut SecretEntry| s.path == *path) {
+            Some(entry) => {
+                entry.attrs.extend(attrs);
+                entry.spans.push(span);
+            }
+            None => secrets.push(SecretEntry {
+                path: path.clone(),
+                groups: Vec::new(),
+                raw_expr: String::new(),
+                armor: false,
+                attrs,
+                spans: vec![span],
+            }),
         }
+    }
 
-        if current_line.ends_with(';') {
-            // Parse: "path".publicKeys = expr;
-            if let Some(dot_pk) = current_line.find("\".publicKeys") {
-                // Find the opening quote explicitly
-                if let Some(open_quote) = current_line.find('"') {
-                    let path = current_line[open_quote + 1..dot_pk].to_string();
-                    let eq = current_line.find('=').unwrap();
-                    let expr = current_line[eq + 1..current_line.len() - 1].trim().to_string();
-                    let group_names: Vec<String> = expr
-                        .split("++")
-                        .map(|s| s.trim().to_string())
-                        .filter(|s| !s.is_empty())
-                        .collect();
-                    secrets.push(SecretEntry {
-                        path,
-                        groups: group_names,
-                        raw_expr: expr,
-                    });
+    for entry in &mut secrets {
+        for attr in &entry.attrs {
+            match attr.name.as_str() {
+                "publicKeys" => {
+                    entry.raw_expr = attr.expr.clone();
+                    entry.groups = expr_group_names(&attr.expr);
                 }
+                "armor" => entry.armor = attr.expr == "true",
+                _ => {}
             }
-            current_line.clear();
         }
     }
+    secrets.retain(|s| s.attrs.iter().any(|a| a.name == "publicKeys"));
 
     Ok(ParsedSecrets { groups, secrets })
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum StrKind {
+    Double,
+    Indented,
+}
+
+/// Split the inside of an attrset into top-level `...;` statements, skipping
+/// comments, strings (including `${}` interpolation) and nested brackets.
+/// Returns byte ranges from the first token up to and including the `;`.
+fn split_statements(text: &str) -> Vec<(usize, usize)> {
+    enum Frame {
+        Bracket,
+        Interp(StrKind),
+    }
+
+    let bytes = text.as_bytes();
+    let mut statements = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut in_str: Option<StrKind> = None;
+    let mut start: Option<usize> = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let next = bytes.get(i + 1).copied();
+
+        if let Some(kind) = in_str {
+            match (kind, c, next) {
+                (StrKind::Double, b'\\', _) => i += 1,
+                (StrKind::Double, b'"', _) => in_str = None,
+                (StrKind::Indented, b'\'', Some(b'\'')) => {
+                    // '' followed by $, ' or \ is an escape; otherwise it ends the string
+                    match bytes.get(i + 2) {
+                        Some(b'$') | Some(b'\'') | Some(b'\\') => i += 2,
+                        _ => {
+                            in_str = None;
+                            i += 1;
+                        }
+                    }
+                }
+                (_, b'$', Some(b'{')) => {
+                    stack.push(Frame::Interp(kind));
+                    in_str = None;
+                    i += 1;
+                }
+                _ => {}
+            }
+            i += 1;
+            continue;
+        }
+
+        match (c, next) {
+            (b'#', _) => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            (b'/', Some(b'*')) => {
+                i += 2;
+                while i + 1 < bytes.len() && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        if !c.is_ascii_whitespace() && start.is_none() {
+            start = Some(i);
+        }
+        match (c, next) {
+            (b'"', _) => in_str = Some(StrKind::Double),
+            (b'\'', Some(b'\'')) => {
+                in_str = Some(StrKind::Indented);
+                i += 1;
+            }
+            (b'{', _) | (b'[', _) | (b'(', _) => stack.push(Frame::Bracket),
+            (b'}', _) | (b']', _) | (b')', _) => {
+                if let Some(Frame::Interp(kind)) = stack.pop() {
+                    in_str = Some(kind);
+                }
+            }
+            (b';', _) if stack.is_empty() => {
+                if let Some(s) = start.take() {
+                    statements.push((s, i + 1));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    statements
+}
+
+/// Split `lhs = rhs` at the first `=` outside of quotes.
+fn split_binding(statement: &str) -> Option<(&str, &str)> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in statement.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '=' if !in_quotes => return Some((&statement[..i], &statement[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split an attribute path like `"dir/x.age".publicKeys` into its components.
+fn parse_attr_path(lhs: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut chars = lhs.trim().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_quotes => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            '"' => in_quotes = !in_quotes,
+            '.' if !in_quotes => parts.push(std::mem::take(&mut current)),
+            c if c.is_whitespace() && !in_quotes => {}
+            c => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+/// Collapse whitespace and drop comments outside of string literals, so
+/// multi-line expressions compare and display as a single line.
+fn normalize_expr(expr: &str) -> String {
+    let mut out = String::new();
+    let mut in_quotes = false;
+    let mut chars = expr.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            out.push(c);
+            if c == '\\' {
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            } else if c == '"' {
+                in_quotes = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_quotes = true;
+                out.push(c);
+            }
+            '#' => {
+                while chars.peek().is_some_and(|&n| n != '\n') {
+                    chars.next();
+                }
+            }
+            c if c.is_whitespace() => {
+                if !out.is_empty() && !out.ends_with(' ') {
+                    out.push(' ');
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out.trim_end().to_string()
+}
+
+/// Widen a statement's range to whole lines when nothing else shares them,
+/// so removing it leaves no blank or dangling indentation behind.
+fn line_span(content: &str, start: usize, end: usize) -> (usize, usize) {
+    let line_start = content[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let start = if content[line_start..start].trim().is_empty() { line_start } else { start };
+    let rest = &content[end..];
+    let line_end = rest.find('\n').map(|i| end + i + 1).unwrap_or(content.len());
+    let end = if content[end..line_end].trim().is_empty() { line_end } else { end };
+    (start, end)
+}
+
 pub fn add_secret_entry(content: &str, path: &str, groups: &[&str]) -> String {
+    add_secret_entries(content, &[path], groups, false)
+}
+
+/// Add several entries sharing the same groups in a single edit. Armored
+/// secrets use the attrset form so `armor` sits next to `publicKeys`.
+pub fn add_secret_entries(content: &str, paths: &[&str], groups: &[&str], armor: bool) -> String {
     let expr = groups.join(" ++ ");
-    let new_line = format!("  \"{}\".publicKeys = {};", path, expr);
-    // Insert before the closing "}"
+    let mut statements = String::new();
+    for path in paths {
+        if armor {
+            statements.push_str(&format!("  \"{}\" = {{ publicKeys = {}; armor = true; }};", path, expr));
+        } else {
+            statements.push_str(&format!("  \"{}\".publicKeys = {};", path, expr));
+        }
+        statements.push('\n');
+    }
+    append_statements(content, &statements)
+}
+
+/// Insert raw statement lines just before the closing "}" of the attrset.
+pub fn append_statements(content: &str, statements: &str) -> String {
     let close_brace = content.rfind('}').unwrap();
     let mut result = content[..close_brace].to_string();
     if !result.ends_with('\n') {
         result.push('\n');
     }
-    result.push_str(&new_line);
-    result.push('\n');
+    result.push_str(statements);
+    if !result.ends_with('\n') {
+        result.push('\n');
+    }
     result.push('}');
     if content.ends_with('\n') {
         result.push('\n');
@@ -128,23 +417,627 @@ pub fn add_secret_entry(content: &str, path: &str, groups: &[&str]) -> String {
     result
 }
 
-pub fn remove_secret_entry(content: &str, path: &str) -> String {
-    let search = format!("\"{}\"", path);
-    let mut result_lines: Vec<&str> = Vec::new();
-    let mut skip = false;
-    for line in content.lines() {
-        if line.contains(&search) {
-            skip = true;
+/// The source lines declaring a secret, exactly as written.
+pub fn entry_source(content: &str, entry: &SecretEntry) -> String {
+    entry.spans.iter()
+        .map(|&(start, end)| &content[start..end])
+        .collect()
+}
+
+/// Byte range of the `let` block's bindings, between `let` and `in`.
+fn let_block_range(content: &str) -> Option<(usize, usize)> {
+    let let_start = find_keyword_line(content, "let")?;
+    let in_start = find_keyword_line(&content[let_start..], "in")? + let_start;
+    Some((let_start + "let".len(), in_start))
+}
+
+/// Byte range of the expression bound to `name` in the `let` block, without
+/// surrounding whitespace or the trailing `;`.
+fn group_binding_span(content: &str, name: &str) -> Option<(usize, usize)> {
+    let (start, end) = let_block_range(content)?;
+    let block = &content[start..end];
+    for (s, e) in split_statements(block) {
+        let statement = &block[s..e - 1];
+        let Some((lhs, rhs)) = split_binding(statement) else {
+            continue;
+        };
+        if lhs.trim() != name {
+            continue;
+        }
+        let trimmed = rhs.trim();
+        let offset = start + s + (rhs.as_ptr() as usize - statement.as_ptr() as usize)
+            + (rhs.len() - rhs.trim_start().len());
+        return Some((offset, offset + trimmed.len()));
+    }
+    None
+}
+
+/// Replace the definition of a `let` binding, or add the binding at the end
+/// of the `let` block when it does not exist yet.
+pub fn set_group_definition(content: &str, name: &str, definition: &str) -> Result<String, String> {
+    let definition = definition.trim().trim_end_matches(';').trim_end();
+    if definition.is_empty() {
+        return Err(format!("Empty definition for {}", name));
+    }
+    if !is_single_expression(definition) {
+        return Err(format!("Invalid definition for {}: expected a single expression such as [ alice bob ] ++ admins", name));
+    }
+    if let Some((start, end)) = group_binding_span(content, name) {
+        let mut result = content.to_string();
+        result.replace_range(start..end, definition);
+        return Ok(result);
+    }
+    let (_, in_start) = let_block_range(content)
+        .ok_or("No let block to add the group to")?;
+    let mut result = content.to_string();
+    result.insert_str(in_start, &format!("  {} = {};\n", name, definition));
+    Ok(result)
+}
+
+/// Whether `definition` can stand as the right side of a binding: written
+/// with its `;` it is one statement, with no `=` outside brackets other than
+/// in comparisons, and its brackets match.
+fn is_single_expression(definition: &str) -> bool {
+    let statement = format!("{};", definition);
+    if !matches!(split_statements(&statement)[..], [(_, end)] if end == statement.len()) {
+        return false;
+    }
+    let tokens = tokenize(definition);
+    let text = |t: &Token| &definition[t.start..t.end];
+    let mut open: Vec<&str> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Punct {
+            continue;
+        }
+        match text(token) {
+            "{" | "[" | "(" => open.push(text(token)),
+            closing @ ("}" | "]" | ")") => {
+                let expected = match closing {
+                    "}" => "{",
+                    "]" => "[",
+                    _ => "(",
+                };
+                if open.pop() != Some(expected) {
+                    return false;
+                }
+            }
+            "=" if open.is_empty() => {
+                // `==`, `!=`, `<=` and `>=` are tokenized as two characters
+                let previous = i.checked_sub(1).map(|p| &tokens[p]).filter(|p| p.end == token.start);
+                let next = tokens.get(i + 1).filter(|n| n.start == token.end);
+                let comparison = previous.is_some_and(|p| matches!(text(p), "=" | "!" | "<" | ">"))
+                    || next.is_some_and(|n| text(n) == "=");
+                if !comparison {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    open.is_empty()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind {
+    Str,
+    Ident,
+    Punct,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+}
+
+/// A rough Nix tokenizer: strings (with their interpolations), identifiers,
+/// `++` and single-character punctuation. Whitespace and comments are skipped.
+fn tokenize(content: &str) -> Vec<Token> {
+    let bytes = content.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let next = bytes.get(i + 1).copied();
+        let start = i;
+        match (c, next) {
+            (b'#', _) => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            (b'/', Some(b'*')) => {
+                i += 2;
+                while i + 1 < bytes.len() && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+            _ if c.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            (b'"', _) | (b'\'', Some(b'\'')) => {
+                let indented = c == b'\'';
+                i += if indented { 2 } else { 1 };
+                let mut depth = 0;
+                while i < bytes.len() {
+                    match (bytes[i], bytes.get(i + 1).copied()) {
+                        (b'\\', _) if !indented => i += 1,
+                        (b'\'', Some(b'\'')) if indented && depth == 0 => {
+                            if matches!(bytes.get(i + 2), Some(b'$') | Some(b'\'') | Some(b'\\')) {
+                                i += 2;
+                            } else {
+                                i += 2;
+                                break;
+                            }
+                            continue;
+                        }
+                        (b'"', _) if !indented && depth == 0 => {
+                            i += 1;
+                            break;
+                        }
+                        (b'$', Some(b'{')) => {
+                            depth += 1;
+                            i += 1;
+                        }
+                        (b'}', _) if depth > 0 => depth -= 1,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                tokens.push(Token { kind: TokenKind::Str, start, end: i.min(bytes.len()) });
+                continue;
+            }
+            (b'+', Some(b'+')) => i += 2,
+            _ if c.is_ascii_alphabetic() || c == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || b"_-'".contains(&bytes[i])) {
+                    i += 1;
+                }
+                tokens.push(Token { kind: TokenKind::Ident, start, end: i });
+                continue;
+            }
+            _ => i += 1,
+        }
+        // Multi-byte characters only ever appear inside strings and comments
+        while i < bytes.len() && !content.is_char_boundary(i) {
+            i += 1;
+        }
+        tokens.push(Token { kind: TokenKind::Punct, start, end: i });
+    }
+    tokens
+}
+
+const KEYWORDS: &[&str] = &["assert", "else", "if", "in", "inherit", "let", "rec", "then", "with"];
+
+/// Whether `name` can be written unquoted as a binding or attribute name.
+pub fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "_-'".contains(c))
+        && !KEYWORDS.contains(&name)
+}
+
+/// Whether `reference` is a binding or an attribute selection such as
+/// `meta.ssh.groups.TECH` or `meta.ssh.nodes."10.0.0.1"`, safe to write into
+/// a `publicKeys` expression. Quoted names may not interpolate.
+pub fn is_group_reference(reference: &str) -> bool {
+    let mut rest = reference;
+    let mut first = true;
+    loop {
+        let end = match rest.strip_prefix('"') {
+            Some(body) if !first => match closing_quote(body) {
+                Some(i) => i + 2,
+                None => return false,
+            },
+            Some(_) => return false,
+            None => {
+                let end = rest.find('.').unwrap_or(rest.len());
+                if !is_identifier(&rest[..end]) {
+                    return false;
+                }
+                end
+            }
+        };
+        match rest[end..].strip_prefix('.') {
+            Some(next) => rest = next,
+            None => return end == rest.len(),
+        }
+        first = false;
+    }
+}
+
+/// Like `is_group_reference`, also accepting a reference wrapped in a list,
+/// as written for an attribute holding a single key.
+pub fn is_key_reference(reference: &str) -> bool {
+    is_group_reference(reference) || reference.strip_prefix('[')
+        .and_then(|r| r.strip_suffix(']'))
+        .is_some_and(|r| is_group_reference(r.trim()))
+}
+
+/// Offset of the `"` closing a string whose body starts at `body`, unless an
+/// unescaped `${` comes first.
+fn closing_quote(body: &str) -> Option<usize> {
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' => return Some(i),
+            '$' if body[i + 1..].starts_with('{') => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Index of the `}` closing the `{` token at `open`.
+fn matching_brace(tokens: &[Token], content: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match &content[token.start..token.end] {
+            "{" if token.kind == TokenKind::Punct => depth += 1,
+            "}" if token.kind == TokenKind::Punct => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Add an `age.secrets` entry to a NixOS module. `name` is the attribute
+/// name as written and `attrs` the entry's attributes, as `(name, expr)`.
+/// With an `age.secrets = { … };` block the entry goes at its end; otherwise
+/// `age.secrets.<name> = { … };` is added at the end of the module.
+pub fn add_age_secret(content: &str, name: &str, attrs: &[(String, String)]) -> Result<String, String> {
+    let tokens = tokenize(content);
+    let text = |i: usize| tokens.get(i).map(|t| &content[t.start..t.end]);
+    let is_seq = |i: usize, seq: &[&str]| seq.iter().enumerate().all(|(j, s)| text(i + j) == Some(*s));
+
+    let block = (0..tokens.len()).find(|&i| is_seq(i, &["age", ".", "secrets", "=", "{"]));
+    let exists = (0..tokens.len()).any(|i| is_seq(i, &["age", ".", "secrets", ".", name]))
+        || block.and_then(|b| matching_brace(&tokens, content, b + 4).map(|close| (b + 5, close)))
+            .is_some_and(|(start, close)| {
+                let mut depth = 0;
+                (start..close).any(|i| {
+                    match text(i) {
+                        Some("{") | Some("[") | Some("(") => depth += 1,
+                        Some("}") | Some("]") | Some(")") => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0 && text(i) == Some(name) && matches!(text(i + 1), Some("=") | Some("."))
+                })
+            });
+    if exists {
+        return Err(format!("age.secrets.{} is already declared in this module", name));
+    }
+
+    let (close, attr) = match block {
+        Some(b) => (matching_brace(&tokens, content, b + 4), name.to_string()),
+        None => (
+            (0..tokens.len()).rev().find(|&i| tokens[i].kind == TokenKind::Punct && text(i) == Some("}")),
+            format!("age.secrets.{}", name),
+        ),
+    };
+    let close = close.ok_or("No attribute set found in the module")?;
+    let brace = tokens[close].start;
+    let line_start = content[..brace].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let brace_indent = &content[line_start..brace];
+    let own_line = brace_indent.trim().is_empty();
+    let indent = if own_line { format!("{}  ", brace_indent) } else { "  ".to_string() };
+
+    let mut entry = format!("{}{} = {{\n", indent, attr);
+    for (key, value) in attrs {
+        entry.push_str(&format!("{}  {} = {};\n", indent, key, value));
+    }
+    entry.push_str(&format!("{}}};\n", indent));
+
+    Ok(if own_line {
+        format!("{}{}{}", &content[..line_start], entry, &content[line_start..])
+    } else {
+        format!("{}\n{}{}", content[..brace].trim_end(), entry, &content[brace..])
+    })
+}
+
+/// Bindings and attribute selections an expression refers to, such as
+/// `admins` or `meta.ssh.groups.TECH`, in order of first use.
+pub fn references(expr: &str) -> Vec<String> {
+    let tokens = tokenize(expr);
+    let text = |i: usize| tokens.get(i).map(|t| &expr[t.start..t.end]);
+    let mut refs: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let selected = i > 0 && text(i - 1) == Some(".");
+        if tokens[i].kind != TokenKind::Ident || selected || KEYWORDS.contains(&text(i).unwrap_or_default()) {
+            i += 1;
+            continue;
+        }
+        let mut path = text(i).unwrap_or_default().to_string();
+        while text(i + 1) == Some(".") && tokens.get(i + 2).is_some_and(|t| t.kind == TokenKind::Ident) {
+            path.push('.');
+            path.push_str(text(i + 2).unwrap_or_default());
+            i += 2;
+        }
+        // Attribute names on the left of `=` in an attrset are not references
+        if text(i + 1) != Some("=") && !refs.contains(&path) {
+            refs.push(path);
         }
-        if skip {
-            if line.trim_end().ends_with(';') {
-                skip = false;
+        i += 1;
+    }
+    refs
+}
+
+/// Names in a leading `{ meta, ... }:` argument set.
+pub fn function_args(content: &str) -> Vec<String> {
+    let tokens = tokenize(content);
+    let text = |i: usize| tokens.get(i).map(|t| &content[t.start..t.end]);
+    if text(0) != Some("{") {
+        return Vec::new();
+    }
+    let Some(close) = (1..tokens.len()).find(|&i| text(i) == Some("}")) else {
+        return Vec::new();
+    };
+    if text(close + 1) != Some(":") {
+        return Vec::new();
+    }
+    (1..close)
+        .filter(|&i| tokens[i].kind == TokenKind::Ident && matches!(text(i + 1), Some(",") | Some("}") | Some("?")))
+        .filter(|&i| text(i - 1) != Some("?"))
+        .map(|i| text(i).unwrap_or_default().to_string())
+        .collect()
+}
+
+/// A key-like string literal in the declarations file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyLiteral {
+    pub value: String,
+    /// The `let` binding whose whole value is this key, if any.
+    pub binding: Option<String>,
+}
+
+/// Every string literal that looks like a public key.
+pub fn key_literals(content: &str) -> Vec<KeyLiteral> {
+    let tokens = tokenize(content);
+    let text = |i: usize| tokens.get(i).map(|t| &content[t.start..t.end]);
+    tokens.iter().enumerate()
+        .filter(|(_, t)| t.kind == TokenKind::Str)
+        .filter_map(|(idx, t)| {
+            let value = string_value(content, t)?;
+            let looks_like_key = ["ssh-", "ecdsa-", "sk-", "age1"].iter().any(|p| value.starts_with(p));
+            if !looks_like_key {
+                return None;
             }
+            let binding = (idx >= 2 && text(idx - 1) == Some("=") && text(idx + 1) == Some(";")
+                && tokens[idx - 2].kind == TokenKind::Ident)
+                .then(|| text(idx - 2).unwrap_or_default().to_string());
+            Some(KeyLiteral { value, binding })
+        })
+        .collect()
+}
+
+/// Apply non-overlapping `(start, end, replacement)` edits.
+fn apply_edits(content: &str, mut edits: Vec<(usize, usize, String)>) -> String {
+    edits.sort_by_key(|e| std::cmp::Reverse(e.0));
+    let mut result = content.to_string();
+    let mut limit = usize::MAX;
+    for (start, end, replacement) in edits {
+        if end > limit {
+            continue;
+        }
+        result.replace_range(start..end, &replacement);
+        limit = start;
+    }
+    result
+}
+
+/// Unquote a plain double-quoted string token.
+fn string_value(content: &str, token: &Token) -> Option<String> {
+    let raw = content[token.start..token.end].strip_prefix('"')?.strip_suffix('"')?;
+    (!raw.contains("${")).then(|| raw.replace("\\\"", "\"").replace("\\\\", "\\"))
+}
+
+/// Whether the token at `idx` sits directly inside a list literal.
+fn in_list(content: &str, tokens: &[Token], idx: usize) -> bool {
+    let mut depth = 0;
+    for t in tokens[..idx].iter().rev() {
+        match &content[t.start..t.end] {
+            "]" | "}" | ")" => depth += 1,
+            "[" if depth == 0 => return true,
+            "{" | "(" if depth == 0 => return false,
+            "[" | "{" | "(" => depth -= 1,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Remove a single-token expression from wherever it appears: list elements
+/// are dropped, `++` operands are cut together with the operator, and a
+/// whole binding value is replaced by an empty list.
+fn remove_operand(content: &str, tokens: &[Token], idx: usize) -> (usize, usize, String) {
+    let token = tokens[idx];
+    let text = |i: usize| tokens.get(i).map(|t| &content[t.start..t.end]);
+    if in_list(content, tokens, idx) {
+        let (start, end) = line_span(content, token.start, token.end);
+        // Keep the separating space when other elements share the line
+        let end = if end == token.end && content[end..].starts_with(' ') { end + 1 } else { end };
+        return (start, end, String::new());
+    }
+    if text(idx + 1) == Some("++") {
+        return (token.start, tokens[idx + 2].start, String::new());
+    }
+    if idx >= 1 && text(idx - 1) == Some("++") {
+        return (tokens[idx - 2].end, token.end, String::new());
+    }
+    (token.start, token.end, "[ ]".to_string())
+}
+
+/// Remove a public key from the declarations: every string literal holding
+/// it (compared without the key comment) is dropped from its list, and `let`
+/// bindings that are exactly that key are removed along with their uses.
+/// Returns the new content and the names of the removed bindings.
+pub fn remove_key(content: &str, key: &str) -> (String, Vec<String>) {
+    let target = crate::keys::normalize_key(key);
+    let tokens = tokenize(content);
+    let text = |i: usize| tokens.get(i).map(|t| &content[t.start..t.end]);
+
+    let mut edits = Vec::new();
+    let mut removed_bindings = Vec::new();
+    for (idx, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Str {
             continue;
         }
-        result_lines.push(line);
+        let Some(value) = string_value(content, token) else {
+            continue;
+        };
+        if crate::keys::normalize_key(&value) != target {
+            continue;
+        }
+        let is_binding = idx >= 2 && text(idx - 1) == Some("=") && text(idx + 1) == Some(";")
+            && tokens[idx - 2].kind == TokenKind::Ident;
+        if is_binding {
+            let name = tokens[idx - 2];
+            let (start, end) = line_span(content, name.start, tokens[idx + 1].end);
+            edits.push((start, end, String::new()));
+            removed_bindings.push(content[name.start..name.end].to_string());
+        } else {
+            edits.push(remove_operand(content, &tokens, idx));
+        }
+    }
+    let content = apply_edits(content, edits);
+    if removed_bindings.is_empty() {
+        return (content, removed_bindings);
+    }
+
+    // Drop references to the removed bindings
+    let tokens = tokenize(&content);
+    let text = |i: usize| tokens.get(i).map(|t| &content[t.start..t.end]);
+    let mut edits = Vec::new();
+    for (idx, token) in tokens.iter().enumerate() {
+        let is_reference = token.kind == TokenKind::Ident
+            && removed_bindings.iter().any(|b| b == &content[token.start..token.end])
+            && (idx == 0 || text(idx - 1) != Some("."))
+            && text(idx + 1) != Some("=")
+            && text(idx + 1) != Some(".");
+        if is_reference {
+            edits.push(remove_operand(&content, &tokens, idx));
+        }
+    }
+    (apply_edits(&content, edits), removed_bindings)
+}
+
+/// Add a public key to a `let` binding that holds a list of keys. A list
+/// literal gets the key as a new element (on its own line when the list spans
+/// several lines); any other expression is extended with `++ [ "key" ]`.
+/// A missing binding is created as a one-element list.
+pub fn add_key_to_group(content: &str, group: &str, key: &str) -> Result<String, String> {
+    let literal = format!("\"{}\"", key.replace('\\', "\\\\").replace('"', "\\\"").replace("${", "\\${"));
+    let Some((start, end)) = group_binding_span(content, group) else {
+        return set_group_definition(content, group, &format!("[ {} ]", literal));
+    };
+    let definition = &content[start..end];
+    let tokens = tokenize(definition);
+    let text = |t: &Token| &definition[t.start..t.end];
+
+    if tokens.len() == 1 && tokens[0].kind == TokenKind::Str {
+        return Err(format!("{} is a single key, not a group", group));
+    }
+    if tokens.iter().any(|t| t.kind == TokenKind::Str && string_value(definition, t)
+        .is_some_and(|v| crate::keys::normalize_key(&v) == crate::keys::normalize_key(key)))
+    {
+        return Ok(content.to_string());
+    }
+
+    // A single list literal: its opening bracket closes at the very end
+    let mut depth = 0;
+    let mut closes_at = None;
+    for (i, t) in tokens.iter().enumerate() {
+        match text(t) {
+            "[" | "{" | "(" => depth += 1,
+            "]" | "}" | ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    closes_at = Some(i);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let is_list = tokens.first().map(text) == Some("[") && closes_at == Some(tokens.len() - 1);
+
+    let mut result = content.to_string();
+    if is_list {
+        let close = start + tokens[tokens.len() - 1].start;
+        let before = &content[start..close];
+        let bracket_line_start = content[..close].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let closing_indent = &content[bracket_line_start..close];
+        if before.contains('\n') && closing_indent.trim().is_empty() {
+            // Indent like the previous element, or one level past the bracket
+            let element_indent = before.lines().rev()
+                .find(|l| !l.trim().is_empty() && l.trim() != "[")
+                .map(|l| l[..l.len() - l.trim_start().len()].to_string())
+                .unwrap_or_else(|| format!("{}  ", closing_indent));
+            result.insert_str(bracket_line_start, &format!("{}{}\n", element_indent, literal));
+        } else {
+            let insert_at = content[..close].trim_end().len();
+            result.insert_str(insert_at, &format!(" {}", literal));
+        }
+    } else {
+        result.insert_str(end, &format!(" ++ [ {} ]", literal));
+    }
+    Ok(result)
+}
+
+/// Point every statement declaring `from` at `to` instead, keeping the rest
+/// of each statement (publicKeys, armor, comments) as written.
+pub fn rename_secret_entry(content: &str, from: &str, to: &str) -> Result<String, String> {
+    let parsed = parse_meta_secrets(content)?;
+    if parsed.secrets.iter().any(|s| s.path == to) {
+        return Err(format!("{} is already declared", to));
+    }
+    let entry = parsed.secrets.iter()
+        .find(|s| s.path == from)
+        .ok_or_else(|| format!("{} is not declared", from))?;
+
+    let quoted_from = format!("\"{}\"", from);
+    let quoted_to = format!("\"{}\"", to);
+    let mut spans = entry.spans.clone();
+    spans.sort_by_key(|span| std::cmp::Reverse(span.0));
+    let mut result = content.to_string();
+    for (start, end) in spans {
+        let statement = &content[start..end];
+        if let Some(pos) = statement.find(&quoted_from) {
+            result.replace_range(start + pos..start + pos + quoted_from.len(), &quoted_to);
+        }
+    }
+    Ok(result)
+}
+
+/// Remove every statement declaring `path`. Other entries are untouched even
+/// when their paths share a prefix with `path`.
+pub fn remove_secret_entry(content: &str, path: &str) -> String {
+    let Ok(parsed) = parse_meta_secrets(content) else {
+        return content.to_string();
+    };
+    let Some(entry) = parsed.secrets.iter().find(|s| s.path == path) else {
+        return content.to_string();
+    };
+
+    let mut spans = entry.spans.clone();
+    spans.sort_by_key(|span| std::cmp::Reverse(span.0));
+    let mut result = content.to_string();
+    for (start, end) in spans {
+        result.replace_range(start..end, "");
     }
-    result_lines.join("\n") + if content.ends_with('\n') { "\n" } else { "" }
+    result
 }
 
 #[cfg(test)]
@@ -190,6 +1083,25 @@ in
         assert_eq!(bq.groups, vec!["tech"]);
     }
 
+    #[test]
+    fn test_parse_standard_secrets_nix() {
+        let content = r#"let
+  user1 = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIL0pU user1";
+  system1 = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIPJDy system1";
+  users = [ user1 ];
+in
+{
+  "secret1.age".publicKeys = [ user1 system1 ];
+  "secret2.age".publicKeys = users ++ [ system1 ];
+}
+"#;
+        let result = parse_meta_secrets(content).unwrap();
+        let group_names: Vec<&str> = result.groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(group_names, vec!["user1", "system1", "users"]);
+        assert_eq!(result.secrets[0].groups, vec!["user1", "system1"]);
+        assert_eq!(result.secrets[1].groups, vec!["users", "system1"]);
+    }
+
     #[test]
     fn test_add_secret_entry() {
         let new_content = add_secret_entry(
@@ -201,6 +1113,18 @@ in
         assert!(new_content.contains("GEMINI_API_KEY"));
     }
 
+    #[test]
+    fn test_add_secret_entries() {
+        let new_content = add_secret_entries(
+            SAMPLE_NIX,
+            &["app/A.age", "app/B.age"],
+            &["tech"],
+            false,
+        );
+        assert!(new_content.contains("  \"app/A.age\".publicKeys = tech;\n  \"app/B.age\".publicKeys = tech;\n}"));
+        assert_eq!(parse_meta_secrets(&new_content).unwrap().secrets.len(), 4);
+    }
+
     #[test]
     fn test_remove_secret_entry() {
         let new_content = remove_secret_entry(
@@ -211,6 +1135,216 @@ in
         assert!(new_content.contains("GEMINI_API_KEY"));
     }
 
+    #[test]
+    fn test_parse_attrset_entries_with_armor() {
+        let content = r#"{ meta }:
+let
+  tech = meta.ssh.groups.TECH;
+in
+{
+  "a.age" = {
+    publicKeys = tech ++ [ "ssh-ed25519 AAAA; not-a-terminator" ]; # comment
+    armor = true;
+  };
+  "b.age".publicKeys = tech;
+  "b.age".armor = false;
+}"#;
+        let result = parse_meta_secrets(content).unwrap();
+        assert_eq!(result.secrets.len(), 2);
+        assert!(result.secrets[0].armor);
+        assert_eq!(result.secrets[0].raw_expr, r#"tech ++ [ "ssh-ed25519 AAAA; not-a-terminator" ]"#);
+        assert!(!result.secrets[1].armor);
+        assert_eq!(result.secrets[1].attrs.len(), 2);
+
+        let removed = remove_secret_entry(content, "a.age");
+        assert_eq!(removed, content.replace(
+            "  \"a.age\" = {\n    publicKeys = tech ++ [ \"ssh-ed25519 AAAA; not-a-terminator\" ]; # comment\n    armor = true;\n  };\n",
+            "",
+        ));
+        assert_eq!(parse_meta_secrets(&removed).unwrap().secrets.len(), 1);
+
+        let armored = add_secret_entries(content, &["c.age"], &["tech"], true);
+        let c = parse_meta_secrets(&armored).unwrap().secrets.into_iter().find(|s| s.path == "c.age").unwrap();
+        assert!(c.armor);
+        assert_eq!(c.groups, vec!["tech"]);
+    }
+
+    #[test]
+    fn test_remove_secret_entry_exact_path() {
+        let content = "{\n  \"api.age\".publicKeys = tech;\n  \"old/api.age\".publicKeys = tech;\n}\n";
+        let new_content = remove_secret_entry(content, "api.age");
+        assert_eq!(new_content, "{\n  \"old/api.age\".publicKeys = tech;\n}\n");
+    }
+
+    #[test]
+    fn test_set_group_definition() {
+        let updated = set_group_definition(SAMPLE_NIX, "ciRunner", "[ ]").unwrap();
+        assert!(updated.contains("  ciRunner = [ ];\n  identity = ["));
+        assert_eq!(parse_meta_secrets(&updated).unwrap().groups.len(), 3);
+
+        let multiline = set_group_definition(SAMPLE_NIX, "identity", "tech").unwrap();
+        assert!(multiline.contains("  identity = tech;\nin\n"));
+
+        let added = set_group_definition(SAMPLE_NIX, "ops", "tech ++ ciRunner;").unwrap();
+        assert!(added.contains("  ];\n  ops = tech ++ ciRunner;\nin\n"));
+        assert!(set_group_definition("{ \"a.age\".publicKeys = [ ]; }", "x", "[ ]").is_err());
+
+        for invalid in [
+            "[ alice ]; evil = [ ]",
+            "[ ]; }",
+            "alice = bob",
+            "[ alice",
+            "alice ]",
+            "[ alice }",
+            "[ \"ssh-ed25519 AAAA ]",
+            "tech # note",
+        ] {
+            assert!(set_group_definition(SAMPLE_NIX, "ops", invalid).is_err(), "{}", invalid);
+        }
+        for valid in [
+            "[ \"a;b=c\" ] ++ tech",
+            "builtins.filter (k: k != \"x\") tech",
+            "if tech == [ ] then ciRunner else tech",
+            "[ { a = 1; }.a ]",
+            "# admins\n[ alice ]",
+        ] {
+            assert!(set_group_definition(SAMPLE_NIX, "ops", valid).is_ok(), "{}", valid);
+        }
+    }
+
+    #[test]
+    fn test_remove_key() {
+        let content = r#"let
+  alice = "ssh-ed25519 AAAAalice alice@laptop";
+  bob = "ssh-ed25519 AAAAbob";
+  admins = [ alice bob ];
+  ops = [
+    "ssh-ed25519 AAAAalice"
+    "ssh-ed25519 AAAAcarol"
+  ];
+in
+{
+  "a.age".publicKeys = admins ++ ops;
+  "b.age".publicKeys = [ alice ];
+  "c.age".publicKeys = alice ++ [ bob ];
+  "d.age".publicKeys = [ bob ] ++ alice;
+  # "ssh-ed25519 AAAAalice" in a comment stays
+}
+"#;
+        let (updated, removed) = remove_key(content, "ssh-ed25519 AAAAalice other-comment");
+        assert_eq!(removed, vec!["alice"]);
+        assert_eq!(updated, r#"let
+  bob = "ssh-ed25519 AAAAbob";
+  admins = [ bob ];
+  ops = [
+    "ssh-ed25519 AAAAcarol"
+  ];
+in
+{
+  "a.age".publicKeys = admins ++ ops;
+  "b.age".publicKeys = [ ];
+  "c.age".publicKeys = [ bob ];
+  "d.age".publicKeys = [ bob ];
+  # "ssh-ed25519 AAAAalice" in a comment stays
+}
+"#);
+        assert_eq!(parse_meta_secrets(&updated).unwrap().secrets.len(), 4);
+    }
+
+    #[test]
+    fn test_add_key_to_group() {
+        let content = "let\n  alice = \"ssh-ed25519 AAAAalice\";\n  admins = [ alice ];\n  empty = [ ];\n  ops = [\n    \"ssh-ed25519 AAAAcarol\"\n  ];\n  tech = meta.ssh.groups.TECH;\nin\n{\n}\n";
+        let key = "ssh-ed25519 AAAAdave dave@host";
+
+        let admins = add_key_to_group(content, "admins", key).unwrap();
+        assert!(admins.contains("  admins = [ alice \"ssh-ed25519 AAAAdave dave@host\" ];\n"));
+        let empty = add_key_to_group(content, "empty", key).unwrap();
+        assert!(empty.contains("  empty = [ \"ssh-ed25519 AAAAdave dave@host\" ];\n"));
+        let ops = add_key_to_group(content, "ops", key).unwrap();
+        assert!(ops.contains("  ops = [\n    \"ssh-ed25519 AAAAcarol\"\n    \"ssh-ed25519 AAAAdave dave@host\"\n  ];\n"));
+        let tech = add_key_to_group(content, "tech", key).unwrap();
+        assert!(tech.contains("  tech = meta.ssh.groups.TECH ++ [ \"ssh-ed25519 AAAAdave dave@host\" ];\n"));
+        let new = add_key_to_group(content, "newgroup", key).unwrap();
+        assert!(new.contains("  newgroup = [ \"ssh-ed25519 AAAAdave dave@host\" ];\nin\n"));
+
+        assert!(add_key_to_group(content, "alice", key).is_err());
+        assert_eq!(add_key_to_group(&ops, "ops", "ssh-ed25519 AAAAdave"), Ok(ops.clone()));
+    }
+
+    #[test]
+    fn test_group_references() {
+        assert!(is_group_reference("TECH"));
+        assert!(is_group_reference("meta.ssh.nodes.ci-runner"));
+        assert!(!is_group_reference("meta..ssh"));
+        assert!(is_group_reference("meta.ssh.groups.\"a b\""));
+        assert!(is_group_reference("meta.ssh.nodes.\"10.0.0.1\".key"));
+        assert!(is_group_reference("meta.\"a\\\"b\""));
+        assert!(!is_group_reference("meta.\"${builtins.abort \"x\"}\""));
+        assert!(!is_group_reference("meta.\"open"));
+        assert!(!is_group_reference("\"meta\".ssh"));
+        assert!(!is_group_reference("meta.\"a\"b"));
+        assert!(is_key_reference("[ meta.ssh.nodes.ci ]"));
+        assert!(!is_key_reference("[ meta.ssh.nodes.ci \"x\" ]"));
+        assert!(!is_group_reference("let"));
+        assert!(!is_group_reference("[ x ]"));
+
+        let content = add_secret_entry(SAMPLE_NIX, "new.age", &["meta.ssh.groups.TECH", "admins"]);
+        let parsed = parse_meta_secrets(&content).unwrap();
+        let entry = parsed.secrets.iter().find(|s| s.path == "new.age").unwrap();
+        assert_eq!(entry.groups, vec!["meta.ssh.groups.TECH", "admins"]);
+    }
+
+    #[test]
+    fn test_add_age_secret() {
+        let attrs = vec![
+            ("file".to_string(), "../secrets/db.age".to_string()),
+            ("owner".to_string(), "\"postgres\"".to_string()),
+        ];
+        let module = "{ config, ... }:\n{\n  services.postgresql.enable = true;\n}\n";
+        assert_eq!(
+            add_age_secret(module, "db", &attrs).unwrap(),
+            "{ config, ... }:\n{\n  services.postgresql.enable = true;\n  age.secrets.db = {\n    file = ../secrets/db.age;\n    owner = \"postgres\";\n  };\n}\n"
+        );
+
+        let module = "{\n  age.secrets = {\n    api.file = ../secrets/api.age;\n  };\n  # }\n}\n";
+        assert_eq!(
+            add_age_secret(module, "db", &attrs).unwrap(),
+            "{\n  age.secrets = {\n    api.file = ../secrets/api.age;\n    db = {\n      file = ../secrets/db.age;\n      owner = \"postgres\";\n    };\n  };\n  # }\n}\n"
+        );
+        assert!(add_age_secret(module, "api", &attrs).is_err());
+        assert!(add_age_secret("{ age.secrets.db.file = ./db.age; }", "db", &attrs).is_err());
+        assert_eq!(add_age_secret("{ }", "db", &attrs[..1]).unwrap(), "{\n  age.secrets.db = {\n    file = ../secrets/db.age;\n  };\n}");
+    }
+
+    #[test]
+    fn test_references() {
+        assert_eq!(references("admins ++ meta.ssh.groups.TECH ++ [ ci \"ssh-ed25519 AAAA\" ]"),
+            vec!["admins", "meta.ssh.groups.TECH", "ci"]);
+        assert_eq!(references("builtins.attrValues meta.ssh.nodes"), vec!["builtins.attrValues", "meta.ssh.nodes"]);
+        assert!(references("[ \"age1xyz\" ]").is_empty());
+    }
+
+    #[test]
+    fn test_function_args_and_key_literals() {
+        assert_eq!(function_args(SAMPLE_NIX), vec!["meta"]);
+        assert_eq!(function_args("{ meta, pkgs ? null, ... }:\n{ }"), vec!["meta", "pkgs"]);
+        assert!(function_args("let x = 1; in { }").is_empty());
+
+        let literals = key_literals("let\n  a = \"ssh-ed25519 AAAAa\";\n  b = [ \"age1xyz\" \"not a key\" ];\nin { }");
+        assert_eq!(literals, vec![
+            KeyLiteral { value: "ssh-ed25519 AAAAa".to_string(), binding: Some("a".to_string()) },
+            KeyLiteral { value: "age1xyz".to_string(), binding: None },
+        ]);
+    }
+
+    #[test]
+    fn test_rename_secret_entry() {
+        let renamed = rename_secret_entry(SAMPLE_NIX, "watch/GEMINI_API_KEY.age", "watch/GEMINI.age").unwrap();
+        assert!(renamed.contains("  \"watch/GEMINI.age\".publicKeys =\n    tech ++ ciRunner ++ identity;"));
+        assert!(rename_secret_entry(SAMPLE_NIX, "watch/GEMINI_API_KEY.age", "bigquery/sa-data-scripts.json.age").is_err());
+        assert!(rename_secret_entry(SAMPLE_NIX, "missing.age", "x.age").is_err());
+    }
+
     #[test]
     fn test_parse_real_file() {
         let path = std::env::var("TEST_META_SECRETS_PATH").ok();
@@ -0,0 +1,136 @@
+use std::path::{Component, Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::config;
+use crate::nix_parser;
+
+/// Options of a generated `age.secrets.<name>` declaration. Unset options
+/// are left out, so the agenix defaults apply. `path` may contain `{name}`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct AgeSecretOptions {
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub mode: Option<String>,
+    pub path: Option<String>,
+}
+
+impl AgeSecretOptions {
+    /// These options, with unset ones taken from `defaults`.
+    pub fn or(&self, defaults: &AgeSecretOptions) -> AgeSecretOptions {
+        let pick = |a: &Option<String>, b: &Option<String>| a.clone().or_else(|| b.clone()).filter(|v| !v.is_empty());
+        AgeSecretOptions {
+            owner: pick(&self.owner, &defaults.owner),
+            group: pick(&self.group, &defaults.group),
+            mode: pick(&self.mode, &defaults.mode),
+            path: pick(&self.path, &defaults.path),
+        }
+    }
+}
+
+/// Defaults for generated declarations, from the config file.
+pub fn defaults() -> AgeSecretOptions {
+    config::load_config_entry("age_secret_defaults").unwrap_or_default()
+}
+
+pub fn save_defaults(defaults: &AgeSecretOptions) {
+    config::save_config_entry("age_secret_defaults", defaults);
+}
+
+/// The attribute name for a secret: its file name without `.age`, quoted
+/// when it is not a plain identifier.
+pub fn secret_name(path: &str) -> String {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let name = file.strip_suffix(".age").unwrap_or(file);
+    if nix_parser::is_identifier(name) {
+        name.to_string()
+    } else {
+        nix_string(name)
+    }
+}
+
+fn nix_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace("${", "\\${"))
+}
+
+/// A Nix path literal for `target` as seen from a file in `dir`, such as
+/// `../secrets/db.age`. Both paths must be absolute.
+pub fn relative_path_literal(dir: &Path, target: &Path) -> String {
+    let parts = |p: &Path| -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        for c in p.components() {
+            match c {
+                Component::Normal(s) => out.push(s.to_string_lossy().to_string()),
+                Component::ParentDir => {
+                    out.pop();
+                }
+                _ => {}
+            }
+        }
+        out
+    };
+    let from = parts(dir);
+    let to = parts(target);
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut rel = PathBuf::new();
+    for _ in common..from.len() {
+        rel.push("..");
+    }
+    for part in &to[common..] {
+        rel.push(part);
+    }
+    let rel = rel.to_string_lossy().to_string();
+    if rel.starts_with("..") { rel } else { format!("./{}", rel) }
+}
+
+/// Attributes of the declaration, in the order they are written.
+pub fn attrs(file: &str, name: &str, options: &AgeSecretOptions) -> Vec<(String, String)> {
+    let bare = name.trim_matches('"');
+    let mut attrs = vec![("file".to_string(), file.to_string())];
+    for (key, value) in [
+        ("owner", &options.owner),
+        ("group", &options.group),
+        ("mode", &options.mode),
+        ("path", &options.path),
+    ] {
+        if let Some(value) = value {
+            attrs.push((key.to_string(), nix_string(&value.replace("{name}", bare))));
+        }
+    }
+    attrs
+}
+
+/// The standalone `age.secrets.<name> = { … };` snippet.
+pub fn snippet(name: &str, attrs: &[(String, String)]) -> String {
+    let mut out = format!("age.secrets.{} = {{\n", name);
+    for (key, value) in attrs {
+        out.push_str(&format!("  {} = {};\n", key, value));
+    }
+    out.push_str("};\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snippet() {
+        assert_eq!(secret_name("hosts/web/db-password.age"), "db-password");
+        assert_eq!(secret_name("hosts/web/db.password.age"), "\"db.password\"");
+        assert_eq!(relative_path_literal(Path::new("/repo/hosts/web"), Path::new("/repo/secrets/db.age")), "../../secrets/db.age");
+        assert_eq!(relative_path_literal(Path::new("/repo"), Path::new("/repo/secrets/db.age")), "./secrets/db.age");
+
+        let options = AgeSecretOptions { owner: Some("postgres".to_string()), ..Default::default() }
+            .or(&AgeSecretOptions {
+                owner: Some("root".to_string()),
+                mode: Some("0400".to_string()),
+                path: Some("/run/keys/{name}".to_string()),
+                ..Default::default()
+            });
+        let attrs = attrs("../secrets/db.age", "db", &options);
+        assert_eq!(
+            snippet("db", &attrs),
+            "age.secrets.db = {\n  file = ../secrets/db.age;\n  owner = \"postgres\";\n  mode = \"0400\";\n  path = \"/run/keys/db\";\n};\n"
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::Serialize;
+use crate::age_cli;
+use crate::keys::{self, KeyLabels};
+use crate::layout::ProjectLayout;
+use crate::metadata::{self, MetadataFile};
+use crate::nix_eval;
+use crate::nix_parser;
+use crate::tasks;
+use crate::trash::{self, TrashEntry};
+
+/// A change to a single `.age` file. `path` is relative to the secret root,
+/// `file` is the already validated absolute location.
+#[derive(Debug, Clone)]
+pub enum FileOp {
+    /// Encrypt new plaintext, creating the file or replacing its contents.
+    Write { path: String, file: PathBuf, plaintext: String },
+    /// Decrypt with the current identity and encrypt again for the
+    /// recipients the declarations now resolve to.
+    Rekey { path: String, file: PathBuf },
+    /// Move the ciphertext into the project trash, recording its declaration.
+    Trash { path: String, file: PathBuf, entry: TrashEntry },
+    /// Move a trashed ciphertext back into place.
+    Restore { path: String, file: PathBuf, id: String },
+    /// Rename a secret's ciphertext.
+    Move { path: String, file: PathBuf, to_path: String, to_file: PathBuf },
+    /// Replace a plain text file such as a NixOS module, which must still
+    /// hold `base` when the plan is applied.
+    Edit { path: String, file: PathBuf, base: String, content: String },
+}
+
+impl FileOp {
+    /// Progress message for this step.
+    fn describe(&self) -> String {
+        match self {
+            FileOp::Write { path, .. } => format!("Encrypting {}", path),
+            FileOp::Rekey { path, .. } => format!("Re-encrypting {}", path),
+            FileOp::Trash { path, .. } => format!("Moving {} to trash", path),
+            FileOp::Restore { path, .. } => format!("Restoring {}", path),
+            FileOp::Move { path, to_path, .. } => format!("Renaming {} to {}", path, to_path),
+            FileOp::Edit { path, .. } => format!("Updating {}", path),
+        }
+    }
+}
+
+/// How to undo one step of a partially applied plan.
+enum Undo {
+    /// Put a file back to its previous bytes, or remove it if it did not exist.
+    File(PathBuf, Option<Vec<u8>>),
+    /// Move a file back to where it came from.
+    Rename { from: PathBuf, to: PathBuf },
+    RemoveDir(PathBuf),
+}
+
+/// Held while a plan is applied.
+static APPLYING: Mutex<()> = Mutex::new(());
+
+/// Everything a mutating command would do, computed up front so it can be
+/// previewed as a diff and applied later as one unit.
+#[derive(Debug, Clone)]
+pub struct ChangePlan {
+    /// Declarations file content the plan was computed against.
+    base: String,
+    /// New declarations file content, when it changes.
+    declarations: Option<String>,
+    metadata_base: MetadataFile,
+    /// New secret metadata, when it changes.
+    metadata: Option<MetadataFile>,
+    /// Key labels merged into the label file when the plan is applied.
+    labels: KeyLabels,
+    ops: Vec<FileOp>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PlanPreview {
+    pub id: String,
+    /// Unified diff of the declarations file (empty when it is unchanged).
+    pub diff: String,
+    pub create: Vec<String>,
+    pub reencrypt: Vec<String>,
+    pub delete: Vec<String>,
+}
+
+impl ChangePlan {
+    pub fn new(layout: &ProjectLayout) -> Result<Self, String> {
+        Ok(ChangePlan {
+            base: layout.read_declarations()?,
+            declarations: None,
+            metadata_base: metadata::load(layout)?,
+            metadata: None,
+            labels: KeyLabels::new(),
+            ops: Vec::new(),
+        })
+    }
+
+    /// Declarations content the plan started from.
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// Declarations content after the plan is applied.
+    pub fn declarations(&self) -> &str {
+        self.declarations.as_deref().unwrap_or(&self.base)
+    }
+
+    pub fn set_declarations(&mut self, content: String) {
+        self.declarations = if content == self.base { None } else { Some(content) };
+    }
+
+    /// Secret metadata after the plan is applied.
+    pub fn metadata(&self) -> &MetadataFile {
+        self.metadata.as_ref().unwrap_or(&self.metadata_base)
+    }
+
+    pub fn set_metadata(&mut self, metadata: MetadataFile) {
+        self.metadata = if metadata == self.metadata_base { None } else { Some(metadata) };
+    }
+
+    pub fn set_label(&mut self, key: &str, label: String) {
+        self.labels.insert(keys::normalize_key(key), label);
+    }
+
+    /// The label file with this plan's labels merged in.
+    fn merged_labels(&self, layout: &ProjectLayout) -> Result<(KeyLabels, KeyLabels), String> {
+        let current = keys::load_labels(layout)?;
+        let mut merged = current.clone();
+        merged.extend(self.labels.clone());
+        Ok((current, merged))
+    }
+
+    pub fn push(&mut self, op: FileOp) {
+        self.ops.push(op);
+    }
+
+    pub fn preview(&self, id: String, layout: &ProjectLayout) -> PlanPreview {
+        let mut preview = PlanPreview {
+            id,
+            diff: String::new(),
+            create: Vec::new(),
+            reencrypt: Vec::new(),
+            delete: Vec::new(),
+        };
+
+        if let Some(ref new) = self.declarations {
+            preview.diff = unified_diff(layout, &layout.declarations_file, &self.base, new);
+        }
+        if let Some(ref new) = self.metadata {
+            preview.diff.push_str(&unified_diff(
+                layout,
+                &metadata::metadata_path(layout),
+                &self.metadata_base.to_json(),
+                &new.to_json(),
+            ));
+        }
+        if let Some((current, merged)) = self.merged_labels(layout).ok().filter(|_| !self.labels.is_empty()) {
+            if current != merged {
+                let json = |l: &KeyLabels| serde_json::to_string_pretty(l).unwrap_or_default() + "\n";
+                preview.diff.push_str(&unified_diff(layout, &keys::labels_path(layout), &json(&current), &json(&merged)));
+            }
+        }
+
+        for op in &self.ops {
+            match op {
+                FileOp::Write { path, file, .. } if !file.exists() => preview.create.push(path.clone()),
+                FileOp::Write { path, .. } | FileOp::Rekey { path, .. } => preview.reencrypt.push(path.clone()),
+                FileOp::Trash { path, .. } => preview.delete.push(path.clone()),
+                FileOp::Restore { path, .. } => preview.create.push(path.clone()),
+                FileOp::Move { path, to_path, .. } => {
+                    preview.delete.push(path.clone());
+                    preview.create.push(to_path.clone());
+                }
+                FileOp::Edit { file, base, content, .. } => {
+                    preview.diff.push_str(&unified_diff(layout, file, base, content));
+                }
+            }
+        }
+        preview
+    }
+
+    /// Apply the plan. Everything written so far is rolled back on failure.
+    pub fn apply(&self, layout: &ProjectLayout, identity: Option<&Path>) -> Result<(), String> {
+        // Commands run concurrently; one plan at a time, so two plans based on
+        // the same declarations cannot both pass the check below
+        let _applying = APPLYING.lock().unwrap_or_else(|e| e.into_inner());
+        if layout.read_declarations()? != self.base {
+            return Err(format!(
+                "{} changed since this change was previewed; preview it again",
+                layout.declarations_name()
+            ));
+        }
+        if self.metadata.is_some() && metadata::load(layout)? != self.metadata_base {
+            return Err("Secret metadata changed since this change was previewed; preview it again".to_string());
+        }
+        for op in &self.ops {
+            if let FileOp::Edit { path, file, base, .. } = op {
+                if fs::read_to_string(file).unwrap_or_default() != *base {
+                    return Err(format!("{} changed since this change was previewed; preview it again", path));
+                }
+            }
+        }
+
+        // Declarations first, so the rules file resolves the new recipients
+        if let Some(ref new) = self.declarations {
+            layout.write_declarations(new)?;
+        }
+
+        let mut undo: Vec<Undo> = Vec::new();
+        let result = self.apply_file_ops(layout, identity, &mut undo);
+
+        if result.is_err() {
+            for step in undo.iter().rev() {
+                let _ = match step {
+                    Undo::File(file, Some(bytes)) => fs::write(file, bytes),
+                    Undo::File(file, None) => fs::remove_file(file),
+                    Undo::Rename { from, to } => fs::rename(from, to),
+                    Undo::RemoveDir(dir) => fs::remove_dir_all(dir),
+                };
+            }
+            if self.declarations.is_some() {
+                let _ = layout.write_declarations(&self.base);
+            }
+        }
+        result
+    }
+
+    fn apply_file_ops(
+        &self,
+        layout: &ProjectLayout,
+        identity: Option<&Path>,
+        undo: &mut Vec<Undo>,
+    ) -> Result<(), String> {
+        let to_encrypt: Vec<String> = self.ops.iter()
+            .filter_map(|op| match op {
+                FileOp::Write { path, .. } | FileOp::Rekey { path, .. } => Some(path.clone()),
+                FileOp::Trash { .. } | FileOp::Restore { .. } | FileOp::Move { .. } | FileOp::Edit { .. } => None,
+            })
+            .collect();
+        let total = self.ops.len();
+        let recipients = if to_encrypt.is_empty() {
+            Default::default()
+        } else {
+            tasks::progress("Resolving recipients", 0, total);
+            nix_eval::resolve_recipients_batch(layout, &to_encrypt)?
+        };
+        let parsed = nix_parser::parse_meta_secrets(self.declarations())?;
+        let armor = |path: &str| parsed.secrets.iter().any(|s| s.path == path && s.armor);
+        let keys_for = |path: &str| recipients.get(path)
+            .ok_or_else(|| format!("No recipients resolved for {}", path));
+
+        for (i, op) in self.ops.iter().enumerate() {
+            // Cancelling between steps rolls back what was already done
+            tasks::check_cancelled()?;
+            tasks::progress(&op.describe(), i + 1, total);
+            match op {
+                FileOp::Write { path, file, plaintext } => {
+                    undo.push(Undo::File(file.clone(), fs::read(file).ok()));
+                    if let Some(parent) = file.parent() {
+                        fs::create_dir_all(parent)
+                            .map_err(|e| format!("Failed to create directories: {}", e))?;
+                    }
+                    age_cli::encrypt_to_file(plaintext, file, keys_for(path)?, armor(path))?;
+                }
+                FileOp::Rekey { path, file } => {
+                    undo.push(Undo::File(file.clone(), fs::read(file).ok()));
+                    let identity = identity.ok_or("No identity configured; it is needed to re-encrypt secrets")?;
+                    let plaintext = age_cli::decrypt_file(file, identity)?;
+                    age_cli::encrypt_to_file(&plaintext, file, keys_for(path)?, armor(path))?;
+                }
+                FileOp::Trash { file, entry, .. } => {
+                    let dir = trash::put(layout, entry, file)?;
+                    undo.push(Undo::RemoveDir(dir.clone()));
+                    if entry.has_ciphertext {
+                        undo.push(Undo::Rename { from: trash::ciphertext_path(&dir), to: file.clone() });
+                    }
+                }
+                FileOp::Restore { path, file, id } => {
+                    if file.exists() {
+                        return Err(format!("{} already exists", path));
+                    }
+                    let dir = trash::entry_dir(layout, id)?;
+                    let ciphertext = trash::ciphertext_path(&dir);
+                    if ciphertext.exists() {
+                        if let Some(parent) = file.parent() {
+                            fs::create_dir_all(parent)
+                                .map_err(|e| format!("Failed to create directories: {}", e))?;
+                        }
+                        fs::rename(&ciphertext, file)
+                            .map_err(|e| format!("Failed to restore {}: {}", path, e))?;
+                        undo.push(Undo::Rename { from: file.clone(), to: ciphertext });
+                    }
+                }
+                FileOp::Move { to_path, file, to_file, .. } => {
+                    if to_file.exists() {
+                        return Err(format!("{} already exists", to_path));
+                    }
+                    if let Some(parent) = to_file.parent() {
+                        fs::create_dir_all(parent)
+                            .map_err(|e| format!("Failed to create directories: {}", e))?;
+                    }
+                    fs::rename(file, to_file)
+                        .map_err(|e| format!("Failed to rename to {}: {}", to_path, e))?;
+                    undo.push(Undo::Rename { from: to_file.clone(), to: file.clone() });
+                }
+                FileOp::Edit { path, file, content, .. } => {
+                    undo.push(Undo::File(file.clone(), fs::read(file).ok()));
+                    fs::write(file, content)
+                        .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+                }
+            }
+        }
+
+        if let Some(ref new) = self.metadata {
+            let path = metadata::metadata_path(layout);
+            undo.push(Undo::File(path.clone(), fs::read(&path).ok()));
+            metadata::save(layout, new)?;
+        }
+        let (current, merged) = if self.labels.is_empty() {
+            Default::default()
+        } else {
+            self.merged_labels(layout)?
+        };
+        if current != merged {
+            let path = keys::labels_path(layout);
+            undo.push(Undo::File(path.clone(), fs::read(&path).ok()));
+            keys::save_labels(layout, &merged)?;
+        }
+
+        // Trash entries are only dropped once everything else succeeded
+        for op in &self.ops {
+            if let FileOp::Restore { id, .. } = op {
+                trash::remove(layout, id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unified_diff(layout: &ProjectLayout, file: &Path, old: &str, new: &str) -> String {
+    let name = file.strip_prefix(&layout.project_dir)
+        .unwrap_or(file)
+        .to_string_lossy()
+        .to_string();
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", name), &format!("b/{}", name))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::LayoutOverrides;
+
+    const META: &str = "{ meta }:\nlet\n  tech = meta.ssh.groups.TECH;\nin\n{\n  \"a.age\".publicKeys = tech;\n  \"b.age\".publicKeys = tech;\n}\n";
+
+    fn temp_project(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("thoughtseize-plan-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("meta_secrets.nix"), META).unwrap();
+        fs::write(dir.join("secrets.nix"), "import ./meta_secrets.nix { meta = {}; }\n").unwrap();
+        fs::write(dir.join("a.age"), "ciphertext").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_preview_and_apply_trash() {
+        let dir = temp_project("delete");
+        let layout = ProjectLayout::detect(&dir, &LayoutOverrides::default()).unwrap();
+
+        let mut plan = ChangePlan::new(&layout).unwrap();
+        plan.set_declarations(nix_parser::remove_secret_entry(plan.base(), "a.age"));
+        let entry = TrashEntry {
+            id: "1-a".to_string(),
+            path: "a.age".to_string(),
+            public_keys: "tech".to_string(),
+            declaration: "  \"a.age\".publicKeys = tech;\n".to_string(),
+            deleted_at: 1,
+            has_ciphertext: true,
+            metadata: None,
+        };
+        plan.push(FileOp::Trash { path: "a.age".to_string(), file: dir.join("a.age"), entry });
+
+        let preview = plan.preview("plan-1".to_string(), &layout);
+        assert!(preview.diff.starts_with("--- a/meta_secrets.nix\n+++ b/meta_secrets.nix\n"));
+        assert!(preview.diff.contains("\n-  \"a.age\".publicKeys = tech;\n"));
+        assert_eq!(preview.delete, vec!["a.age"]);
+        assert!(preview.create.is_empty() && preview.reencrypt.is_empty());
+
+        // Nothing is touched until the plan is applied
+        assert!(dir.join("a.age").exists());
+        plan.apply(&layout, None).unwrap();
+        assert!(!dir.join("a.age").exists());
+        assert!(!layout.read_declarations().unwrap().contains("a.age"));
+        assert_eq!(trash::list(&layout)[0].path, "a.age");
+
+        // The same plan is stale once the declarations have changed
+        assert!(plan.apply(&layout, None).is_err());
+
+        let mut restore = ChangePlan::new(&layout).unwrap();
+        restore.set_declarations(nix_parser::append_statements(restore.base(), "  \"a.age\".publicKeys = tech;\n"));
+        restore.push(FileOp::Restore { path: "a.age".to_string(), file: dir.join("a.age"), id: "1-a".to_string() });
+        restore.apply(&layout, None).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("a.age")).unwrap(), "ciphertext");
+        assert_eq!(layout.read_declarations().unwrap().matches("a.age").count(), 1);
+        assert!(trash::list(&layout).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
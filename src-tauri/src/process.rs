@@ -0,0 +1,208 @@
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::config;
+use crate::tasks;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Time limits for external programs, in seconds; 0 disables the limit.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Timeouts {
+    pub nix_secs: u64,
+    pub age_secs: u64,
+    pub git_secs: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts { nix_secs: 120, age_secs: 30, git_secs: 30 }
+    }
+}
+
+impl Timeouts {
+    pub fn nix(&self) -> Option<Duration> {
+        limit(self.nix_secs)
+    }
+
+    pub fn age(&self) -> Option<Duration> {
+        limit(self.age_secs)
+    }
+
+    pub fn git(&self) -> Option<Duration> {
+        limit(self.git_secs)
+    }
+}
+
+fn limit(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Timeouts from the config file, falling back to the defaults.
+pub fn timeouts() -> Timeouts {
+    config::load_config_entry("timeouts").unwrap_or_default()
+}
+
+pub fn save_timeouts(timeouts: &Timeouts) {
+    config::save_config_entry("timeouts", timeouts);
+}
+
+/// Run a command to completion, feeding it `input` on stdin. Without input
+/// stdin is closed, so a program that prompts fails instead of waiting. The
+/// child is killed once `timeout` elapses, or when the current task is
+/// cancelled, in which case the call fails with "Cancelled".
+pub fn run(cmd: &mut Command, input: Option<&[u8]>, timeout: Option<Duration>) -> Result<Output, String> {
+    tasks::check_cancelled()?;
+    let program = program_name(cmd);
+
+    let mut child = cmd
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    // Pipes are drained on their own threads so a chatty child cannot block
+    let stdin = child.stdin.take();
+    let input = input.map(|i| i.to_vec());
+    let writer = thread::spawn(move || {
+        if let (Some(mut stdin), Some(input)) = (stdin, input) {
+            let _ = stdin.write_all(&input);
+        }
+    });
+    let stdout = Arc::new(Mutex::new(Vec::new()));
+    let stderr = Arc::new(Mutex::new(Vec::new()));
+    let readers = [
+        drain(child.stdout.take(), stdout.clone()),
+        drain(child.stderr.take(), stderr.clone()),
+    ];
+
+    let child = Arc::new(Mutex::new(child));
+    let task = tasks::current();
+    if let Some(ref task) = task {
+        task.register_child(&child);
+    }
+    let status = wait(&child, timeout);
+    if let Some(ref task) = task {
+        task.unregister_child(&child);
+    }
+
+    // After a kill the pipe threads are left to finish on their own: a
+    // grandchild may still hold the pipes open
+    tasks::check_cancelled()?;
+    let status = match status {
+        Ok(Some(status)) => status,
+        Ok(None) => {
+            let stderr = take(&stderr);
+            let stderr = String::from_utf8_lossy(&stderr);
+            let mut message = format!("{} timed out after {:?}", program, timeout.unwrap_or_default());
+            if !stderr.trim().is_empty() {
+                message.push_str(&format!(": {}", stderr.trim()));
+            }
+            return Err(message);
+        }
+        Err(e) => return Err(format!("Failed to wait for {}: {}", program, e)),
+    };
+
+    let _ = writer.join();
+    for reader in readers {
+        let _ = reader.join();
+    }
+    Ok(Output { status, stdout: take(&stdout), stderr: take(&stderr) })
+}
+
+fn program_name(cmd: &Command) -> String {
+    let program = Path::new(cmd.get_program());
+    program.file_name().unwrap_or(program.as_os_str()).to_string_lossy().to_string()
+}
+
+/// Copy a pipe into a shared buffer as data arrives, so partial output is
+/// available even if the pipe never closes.
+fn drain<R: Read + Send + 'static>(pipe: Option<R>, buf: Arc<Mutex<Vec<u8>>>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let Some(mut pipe) = pipe else { return };
+        let mut chunk = [0u8; 8192];
+        while let Ok(n) = pipe.read(&mut chunk) {
+            if n == 0 {
+                break;
+            }
+            if let Ok(mut buf) = buf.lock() {
+                buf.extend_from_slice(&chunk[..n]);
+            }
+        }
+    })
+}
+
+fn take(buf: &Mutex<Vec<u8>>) -> Vec<u8> {
+    buf.lock().map(|mut b| std::mem::take(&mut *b)).unwrap_or_default()
+}
+
+/// Poll rather than block in `wait`, so `Task::cancel` can take the lock.
+/// Returns `None` when the child was killed for running past `timeout`.
+fn wait(child: &Mutex<Child>, timeout: Option<Duration>) -> Result<Option<ExitStatus>, String> {
+    let started = Instant::now();
+    loop {
+        let mut guard = child.lock()
+            .map_err(|_| "Internal state error".to_string())?;
+        if let Some(status) = guard.try_wait().map_err(|e| e.to_string())? {
+            return Ok(Some(status));
+        }
+        if timeout.is_some_and(|t| started.elapsed() >= t) {
+            let _ = guard.kill();
+            let _ = guard.wait();
+            return Ok(None);
+        }
+        drop(guard);
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::Task;
+
+    #[test]
+    fn test_run_with_input() {
+        let output = run(&mut Command::new("cat"), Some(b"hello"), None).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello");
+    }
+
+    #[test]
+    fn test_cancel_kills_child() {
+        let task = Task::new("t".to_string(), None);
+        let canceller = task.clone();
+        let timer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            canceller.cancel();
+        });
+        let result = tasks::with_task(task, || run(Command::new("sleep").arg("10"), None, None));
+        timer.join().unwrap();
+        assert_eq!(result.unwrap_err(), "Cancelled");
+    }
+
+    #[test]
+    fn test_timeout_kills_child() {
+        let started = Instant::now();
+        let result = run(
+            Command::new("sh").args(["-c", "echo waiting >&2; sleep 10"]),
+            None,
+            Some(Duration::from_millis(200)),
+        );
+        assert_eq!(result.unwrap_err(), "sh timed out after 200ms: waiting");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_stdin_closed_without_input() {
+        let output = run(&mut Command::new("cat"), None, Some(Duration::from_secs(5))).unwrap();
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+}
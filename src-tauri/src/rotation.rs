@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::process::Command;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use crate::layout::ProjectLayout;
+use crate::metadata::MetadataFile;
+use crate::process;
+
+/// Where a secret's last-changed date came from.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeSource {
+    /// The `rotated` timestamp in the metadata file.
+    Metadata,
+    /// The last commit touching the `.age` file.
+    Git,
+    /// The `created` timestamp in the metadata file.
+    Created,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RotationStatus {
+    pub path: String,
+    pub last_changed: DateTime<Utc>,
+    pub source: ChangeSource,
+    pub interval_days: u32,
+    pub due: DateTime<Utc>,
+    /// Negative when overdue.
+    pub days_left: i64,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct RotationReport {
+    pub overdue: Vec<RotationStatus>,
+    pub due_soon: Vec<RotationStatus>,
+    /// Secrets with a known age and interval that are not due yet.
+    pub ok: Vec<RotationStatus>,
+    /// Secrets with no interval, or no date to measure it from.
+    pub unscheduled: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Last commit time of every file below the secret root, from the local git
+/// history. Paths are relative to the secret root.
+pub fn git_last_changed(layout: &ProjectLayout) -> Result<HashMap<String, DateTime<Utc>>, String> {
+    let mut cmd = Command::new("git");
+    cmd.args(["-c", "core.quotepath=off", "log", "--format=@%ct", "--name-only", "--relative", "--", "."])
+        .current_dir(&layout.secret_root);
+    let output = process::run(&mut cmd, None, process::timeouts().git())?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git log failed: {}", stderr.trim()));
+    }
+
+    Ok(parse_git_log(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse `git log --format=@%ct --name-only` output. The log is newest first,
+/// so the first time a path shows up is its last change.
+fn parse_git_log(log: &str) -> HashMap<String, DateTime<Utc>> {
+    let mut changed = HashMap::new();
+    let mut current = None;
+    for line in log.lines() {
+        if let Some(ts) = line.strip_prefix('@') {
+            current = ts.trim().parse::<i64>().ok().and_then(|s| DateTime::from_timestamp(s, 0));
+        } else if !line.is_empty() {
+            if let Some(date) = current {
+                changed.entry(line.to_string()).or_insert(date);
+            }
+        }
+    }
+    changed
+}
+
+/// Classify secrets by rotation due date. An explicit `rotated` date wins over
+/// git history, since re-keying rewrites the file without changing the value.
+pub fn build_report(
+    paths: &[String],
+    metadata: &MetadataFile,
+    git: &HashMap<String, DateTime<Utc>>,
+    default_interval_days: Option<u32>,
+    soon_days: u32,
+    now: DateTime<Utc>,
+) -> RotationReport {
+    let mut report = RotationReport::default();
+    for path in paths {
+        let meta = metadata.get(path);
+        let last_changed = meta.and_then(|m| m.rotated).map(|d| (d, ChangeSource::Metadata))
+            .or_else(|| git.get(path).map(|d| (*d, ChangeSource::Git)))
+            .or_else(|| meta.and_then(|m| m.created).map(|d| (d, ChangeSource::Created)));
+        let interval = meta.and_then(|m| m.rotation_interval_days).or(default_interval_days);
+
+        let (Some((last_changed, source)), Some(interval_days)) = (last_changed, interval) else {
+            report.unscheduled.push(path.clone());
+            continue;
+        };
+        let due = last_changed + Duration::days(interval_days as i64);
+        let days_left = (due - now).num_days();
+        let status = RotationStatus {
+            path: path.clone(),
+            last_changed,
+            source,
+            interval_days,
+            due,
+            days_left,
+        };
+        if due <= now {
+            report.overdue.push(status);
+        } else if days_left < soon_days as i64 {
+            report.due_soon.push(status);
+        } else {
+            report.ok.push(status);
+        }
+    }
+    for list in [&mut report.overdue, &mut report.due_soon, &mut report.ok] {
+        list.sort_by_key(|s| s.due);
+    }
+    report
+}
+
+/// Build the rotation report for a project. A missing git history is not an
+/// error; only metadata dates are used then.
+pub fn report(
+    layout: &ProjectLayout,
+    default_interval_days: Option<u32>,
+    soon_days: u32,
+) -> Result<RotationReport, String> {
+    let paths = layout.age_files()?;
+    let metadata = crate::metadata::load(layout)?;
+    let (git, warning) = match git_last_changed(layout) {
+        Ok(git) => (git, None),
+        Err(e) => (HashMap::new(), Some(e)),
+    };
+    let mut report = build_report(&paths, &metadata, &git, default_interval_days, soon_days, Utc::now());
+    report.warnings.extend(warning);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::SecretMetadata;
+
+    #[test]
+    fn test_parse_git_log() {
+        let log = "@1700000000\n\nwatch/a.age\nwatch/b.age\n@1600000000\n\nwatch/a.age\n";
+        let changed = parse_git_log(log);
+        assert_eq!(changed["watch/a.age"].timestamp(), 1700000000);
+        assert_eq!(changed["watch/b.age"].timestamp(), 1700000000);
+        assert_eq!(changed.len(), 2);
+    }
+
+    #[test]
+    fn test_build_report() {
+        let now: DateTime<Utc> = "2025-06-01T00:00:00Z".parse().unwrap();
+        let mut metadata = MetadataFile::default();
+        metadata.set("rotated.age", SecretMetadata {
+            rotated: Some("2025-05-25T00:00:00Z".parse().unwrap()),
+            rotation_interval_days: Some(10),
+            ..Default::default()
+        });
+        let mut git = HashMap::new();
+        git.insert("rotated.age".to_string(), "2024-01-01T00:00:00Z".parse().unwrap());
+        git.insert("old.age".to_string(), "2025-01-01T00:00:00Z".parse().unwrap());
+        git.insert("fresh.age".to_string(), "2025-05-30T00:00:00Z".parse().unwrap());
+
+        let paths: Vec<String> = ["rotated.age", "old.age", "fresh.age", "untracked.age"]
+            .iter().map(|s| s.to_string()).collect();
+        let report = build_report(&paths, &metadata, &git, Some(90), 7, now);
+
+        assert_eq!(report.overdue.len(), 1);
+        assert_eq!(report.overdue[0].path, "old.age");
+        assert_eq!(report.due_soon.len(), 1);
+        assert_eq!(report.due_soon[0].path, "rotated.age");
+        assert_eq!(report.due_soon[0].source, ChangeSource::Metadata);
+        assert_eq!(report.due_soon[0].days_left, 3);
+        assert_eq!(report.ok[0].path, "fresh.age");
+        assert_eq!(report.unscheduled, vec!["untracked.age".to_string()]);
+
+        let report = build_report(&paths, &metadata, &git, None, 7, now);
+        assert_eq!(report.unscheduled.len(), 3);
+    }
+}
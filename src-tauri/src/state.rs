@@ -1,10 +1,57 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::layout::ProjectLayout;
 use crate::nix_parser::ParsedSecrets;
+use crate::plan::ChangePlan;
+use crate::tasks::Task;
+
+/// Recipients of each secret from the last evaluation of a declarations
+/// file, reused while its content is unchanged.
+pub struct CachedRecipients {
+    pub declarations_file: PathBuf,
+    pub declarations: String,
+    pub secrets: HashMap<String, Vec<String>>,
+}
+
+/// A previewed plan with the secret root it was computed for.
+pub struct PendingPlan {
+    pub layout: ProjectLayout,
+    pub plan: ChangePlan,
+    pub created: Instant,
+}
 
 #[derive(Default)]
 pub struct AppState {
-    pub project_dir: Mutex<Option<PathBuf>>,
+    /// The secret root commands act on.
+    pub layout: Mutex<Option<ProjectLayout>>,
+    /// Every secret root found in the opened directory.
+    pub roots: Mutex<Vec<ProjectLayout>>,
     pub identity_path: Mutex<Option<PathBuf>>,
     pub parsed_secrets: Mutex<Option<ParsedSecrets>>,
+    /// Filled by `check_recipients`; lets `open_project` report stale
+    /// recipients without evaluating.
+    pub recipients: Mutex<Option<CachedRecipients>>,
+    /// Changes previewed with `dry_run`, waiting for `apply_plan`. They may
+    /// hold plaintext, so they expire and are dropped when a project is opened.
+    pub pending_plans: Mutex<HashMap<String, PendingPlan>>,
+    /// Long-running commands currently executing, by task id.
+    pub tasks: Mutex<HashMap<String, Arc<Task>>>,
+}
+
+impl AppState {
+    pub fn layout(&self) -> Result<ProjectLayout, String> {
+        let guard = self.layout.lock()
+            .map_err(|_| "Internal state error".to_string())?;
+        guard.as_ref().cloned().ok_or_else(|| "No project open".to_string())
+    }
+
+    pub fn identity_path(&self) -> Result<PathBuf, String> {
+        let guard = self.identity_path.lock()
+            .map_err(|_| "Internal state error".to_string())?;
+        guard.as_ref().cloned().ok_or_else(|| {
+            "No identity configured. Click the gear icon to select an identity file.".to_string()
+        })
+    }
 }
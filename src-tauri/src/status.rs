@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use crate::consumers;
+use crate::keys;
+use crate::layout::ProjectLayout;
+use crate::process;
+
+const ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GitStatus {
+    Untracked,
+    /// Changed in the working tree.
+    Modified,
+    /// Changed in the index only.
+    Staged,
+}
+
+/// What can be told about a secret without decrypting it.
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct SecretStatus {
+    /// Declared in the rules but there is no `.age` file.
+    pub missing: bool,
+    /// An `.age` file the rules do not declare.
+    pub undeclared: bool,
+    /// Whether the current identity is a recipient; `None` when the file
+    /// header cannot tell, as for age identities.
+    pub decryptable: Option<bool>,
+    /// Whether the file was encrypted for other recipients than the rules
+    /// resolve to; `None` until the rules have been evaluated, see
+    /// `check_recipients`.
+    pub recipients_stale: Option<bool>,
+    /// `None` for files git has no changes for, or outside a repository.
+    pub git: Option<GitStatus>,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// A recipient stanza of an age header, such as `-> ssh-ed25519 <tag> …`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stanza {
+    pub kind: String,
+    pub args: Vec<String>,
+}
+
+/// Parse the recipient stanzas of an age file, binary or armored.
+pub fn header_stanzas(data: &[u8]) -> Option<Vec<Stanza>> {
+    let decoded;
+    let data = if data.starts_with(ARMOR_BEGIN.as_bytes()) {
+        let text = std::str::from_utf8(data).ok()?;
+        let body: String = text.lines()
+            .skip(1)
+            .take_while(|l| !l.starts_with("-----END"))
+            .map(str::trim)
+            .collect();
+        decoded = base64::engine::general_purpose::STANDARD.decode(body).ok()?;
+        &decoded[..]
+    } else {
+        data
+    };
+
+    let mut lines = data.split(|b| *b == b'\n');
+    if lines.next()? != b"age-encryption.org/v1" {
+        return None;
+    }
+    let mut stanzas = Vec::new();
+    for line in lines {
+        let line = std::str::from_utf8(line).ok()?;
+        if line.starts_with("---") {
+            return Some(stanzas);
+        }
+        if let Some(rest) = line.strip_prefix("-> ") {
+            let mut parts = rest.split(' ').map(str::to_string);
+            stanzas.push(Stanza { kind: parts.next()?, args: parts.collect() });
+        }
+    }
+    None
+}
+
+fn read_header(file: &Path) -> Option<Vec<Stanza>> {
+    header_stanzas(&fs::read(file).ok()?)
+}
+
+/// Whether the header was written for exactly `recipients`. SSH stanzas are
+/// matched by key tag; X25519 stanzas carry no key hint, so only their number
+/// is compared.
+pub fn recipients_stale(stanzas: &[Stanza], recipients: &[String]) -> bool {
+    let mut expected_tags: Vec<String> = recipients.iter().filter_map(|k| keys::ssh_tag(k)).collect();
+    let mut actual_tags: Vec<String> = stanzas.iter()
+        .filter(|s| s.kind.starts_with("ssh-"))
+        .filter_map(|s| s.args.first().cloned())
+        .collect();
+    for tags in [&mut expected_tags, &mut actual_tags] {
+        tags.sort();
+        tags.dedup();
+    }
+
+    let age_keys: Vec<&String> = recipients.iter().filter(|k| k.starts_with("age1")).collect();
+    let mut unique_age_keys = age_keys.clone();
+    unique_age_keys.sort();
+    unique_age_keys.dedup();
+    let x25519 = stanzas.iter().filter(|s| s.kind == "X25519").count();
+
+    expected_tags != actual_tags || x25519 < unique_age_keys.len() || x25519 > age_keys.len()
+}
+
+/// Whether `identity_key` can open a file with this header, if the header
+/// says.
+pub fn decryptable(stanzas: &[Stanza], identity_key: &str) -> Option<bool> {
+    match keys::ssh_tag(identity_key) {
+        Some(tag) => Some(stanzas.iter().any(|s| s.kind.starts_with("ssh-") && s.args.first() == Some(&tag))),
+        None if stanzas.iter().any(|s| s.kind == "X25519") => None,
+        None => Some(false),
+    }
+}
+
+/// Parse `git status --porcelain -z` output into statuses by path.
+fn parse_git_status(output: &str) -> HashMap<String, GitStatus> {
+    let mut statuses = HashMap::new();
+    let mut records = output.split('\0');
+    while let Some(record) = records.next() {
+        if record.len() < 4 {
+            continue;
+        }
+        let (code, path) = record.split_at(3);
+        let mut code = code.chars();
+        let (index, worktree) = (code.next().unwrap_or(' '), code.next().unwrap_or(' '));
+        let status = match (index, worktree) {
+            ('?', _) => GitStatus::Untracked,
+            (_, ' ') => GitStatus::Staged,
+            _ => GitStatus::Modified,
+        };
+        // Renames and copies are followed by the original path
+        if index == 'R' || index == 'C' {
+            records.next();
+        }
+        statuses.insert(path.to_string(), status);
+    }
+    statuses
+}
+
+/// Git status of the files below the secret root, by path relative to it.
+/// Empty outside a git checkout.
+fn git_statuses(layout: &ProjectLayout) -> Result<HashMap<String, GitStatus>, String> {
+    let root = consumers::repository_root(&layout.project_dir);
+    if !root.join(".git").exists() {
+        return Ok(HashMap::new());
+    }
+    let prefix = layout.secret_root.strip_prefix(&root)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut cmd = Command::new("git");
+    cmd.args(["status", "--porcelain", "-z", "--untracked-files=all", "--", "."])
+        .current_dir(&layout.secret_root);
+    let output = process::run(&mut cmd, None, process::timeouts().git())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git status failed: {}", stderr.trim()));
+    }
+    Ok(parse_git_status(&String::from_utf8_lossy(&output.stdout)).into_iter()
+        .filter_map(|(path, status)| {
+            let rel = if prefix.is_empty() { Some(path.as_str()) } else { path.strip_prefix(&format!("{}/", prefix)) };
+            rel.map(|r| (r.to_string(), status))
+        })
+        .collect())
+}
+
+/// Status of every secret. `files` are the `.age` files found, `declared`
+/// the paths in the rules, `recipients` the keys each declared path resolved
+/// to in an earlier evaluation, if any. Only file headers, metadata and
+/// `git status` are read, so this stays cheap; problems that leave a flag
+/// unknown are returned as warnings.
+pub fn compute(
+    layout: &ProjectLayout,
+    files: &[String],
+    declared: &[String],
+    recipients: Option<&HashMap<String, Vec<String>>>,
+    identity_key: Option<&str>,
+) -> (HashMap<String, SecretStatus>, Vec<String>) {
+    let mut warnings = Vec::new();
+    let git = git_statuses(layout).unwrap_or_else(|e| {
+        warnings.push(e);
+        HashMap::new()
+    });
+
+    let mut statuses = HashMap::new();
+    for path in files {
+        let file = layout.secret_root.join(path);
+        let stanzas = read_header(&file);
+        let status = SecretStatus {
+            missing: false,
+            undeclared: !declared.contains(path),
+            decryptable: stanzas.as_deref().zip(identity_key).and_then(|(s, k)| decryptable(s, k)),
+            recipients_stale: stanzas.as_deref()
+                .zip(recipients.and_then(|r| r.get(path)))
+                .map(|(s, r)| recipients_stale(s, r)),
+            git: git.get(path).copied(),
+            modified: fs::metadata(&file).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from),
+        };
+        statuses.insert(path.clone(), status);
+    }
+    for path in declared {
+        statuses.entry(path.clone()).or_insert_with(|| SecretStatus { missing: true, ..Default::default() });
+    }
+    (statuses, warnings)
+}
+
+/// For each declared secret with a readable file, whether it was encrypted
+/// for other recipients than the keys the rules now resolve it to.
+pub fn stale_recipients(layout: &ProjectLayout, recipients: &HashMap<String, Vec<String>>) -> HashMap<String, bool> {
+    recipients.iter()
+        .filter_map(|(path, keys)| {
+            let stanzas = read_header(&layout.secret_root.join(path))?;
+            Some((path.clone(), recipients_stale(&stanzas, keys)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ED25519: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl";
+
+    #[test]
+    fn test_header_stanzas() {
+        let tag = keys::ssh_tag(ED25519).unwrap();
+        let header = format!(
+            "age-encryption.org/v1\n-> ssh-ed25519 {} abc\nbody\n-> X25519 def\nbody\n-> x-grease\n\n--- mac\n\x00\x01",
+            tag
+        );
+        let mut data = header.into_bytes();
+        data.extend_from_slice(&[0xff, 0xfe]);
+        let stanzas = header_stanzas(&data).unwrap();
+        let kinds: Vec<&str> = stanzas.iter().map(|s| s.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["ssh-ed25519", "X25519", "x-grease"]);
+
+        let armored = format!(
+            "{}\n{}\n-----END AGE ENCRYPTED FILE-----\n",
+            ARMOR_BEGIN,
+            base64::engine::general_purpose::STANDARD.encode(&data)
+        );
+        assert_eq!(header_stanzas(armored.as_bytes()).unwrap(), stanzas);
+        assert!(header_stanzas(b"not age").is_none());
+
+        assert_eq!(decryptable(&stanzas, ED25519), Some(true));
+        assert_eq!(decryptable(&stanzas, "age1xyz"), None);
+        let age = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p".to_string();
+        assert!(!recipients_stale(&stanzas, &[ED25519.to_string(), age.clone()]));
+        assert!(recipients_stale(&stanzas, &[ED25519.to_string()]));
+        assert!(recipients_stale(&stanzas, &[age]));
+    }
+
+    #[test]
+    fn test_parse_git_status() {
+        let output = "?? new.age\0M  staged.age\0 M changed.age\0R  moved.age\0old.age\0MM both.age\0";
+        let statuses = parse_git_status(output);
+        assert_eq!(statuses["new.age"], GitStatus::Untracked);
+        assert_eq!(statuses["staged.age"], GitStatus::Staged);
+        assert_eq!(statuses["changed.age"], GitStatus::Modified);
+        assert_eq!(statuses["moved.age"], GitStatus::Staged);
+        assert_eq!(statuses["both.age"], GitStatus::Modified);
+        assert!(!statuses.contains_key("old.age"));
+    }
+}
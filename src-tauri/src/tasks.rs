@@ -0,0 +1,152 @@
+use std::cell::RefCell;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use serde::Serialize;
+
+/// Payload of the `task-progress` event.
+#[derive(Debug, Serialize, Clone)]
+pub struct TaskProgress {
+    pub task_id: String,
+    pub message: String,
+    pub current: usize,
+    pub total: usize,
+}
+
+type Reporter = Box<dyn Fn(&TaskProgress) + Send + Sync>;
+
+/// A long-running operation. Child processes started while it is current
+/// are registered with it so that `cancel` can kill them.
+pub struct Task {
+    pub id: String,
+    cancelled: AtomicBool,
+    children: Mutex<Vec<Arc<Mutex<Child>>>>,
+    reporter: Option<Reporter>,
+}
+
+impl Task {
+    pub fn new(id: String, reporter: Option<Reporter>) -> Arc<Self> {
+        Arc::new(Task {
+            id,
+            cancelled: AtomicBool::new(false),
+            children: Mutex::new(Vec::new()),
+            reporter,
+        })
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Mark the task cancelled and kill any child process it is waiting on.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if let Ok(children) = self.children.lock() {
+            for child in children.iter() {
+                if let Ok(mut child) = child.lock() {
+                    let _ = child.kill();
+                }
+            }
+        }
+    }
+
+    pub(crate) fn register_child(&self, child: &Arc<Mutex<Child>>) {
+        if let Ok(mut children) = self.children.lock() {
+            children.push(child.clone());
+        }
+        // A cancel that raced with the spawn still has to take effect
+        if self.is_cancelled() {
+            if let Ok(mut child) = child.lock() {
+                let _ = child.kill();
+            }
+        }
+    }
+
+    pub(crate) fn unregister_child(&self, child: &Arc<Mutex<Child>>) {
+        if let Ok(mut children) = self.children.lock() {
+            children.retain(|c| !Arc::ptr_eq(c, child));
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Task>>> = const { RefCell::new(None) };
+}
+
+/// Puts back the task that was current before `with_task`, even if `f`
+/// panics: blocking threads are pooled and reused by later commands.
+struct Restore(Option<Arc<Task>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+/// Run `f` with `task` as the current task of this thread.
+pub fn with_task<R>(task: Arc<Task>, f: impl FnOnce() -> R) -> R {
+    let _restore = Restore(CURRENT.with(|c| c.replace(Some(task))));
+    f()
+}
+
+pub fn current() -> Option<Arc<Task>> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+/// Report progress of the current task, if any.
+pub fn progress(message: &str, current: usize, total: usize) {
+    if let Some(task) = self::current() {
+        if let Some(ref reporter) = task.reporter {
+            reporter(&TaskProgress {
+                task_id: task.id.clone(),
+                message: message.to_string(),
+                current,
+                total,
+            });
+        }
+    }
+}
+
+/// Fail with "Cancelled" once the current task has been cancelled.
+pub fn check_cancelled() -> Result<(), String> {
+    match current() {
+        Some(task) if task.is_cancelled() => Err("Cancelled".to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_and_cancel() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let task = Task::new("t1".to_string(), Some(Box::new(move |p: &TaskProgress| {
+            sink.lock().unwrap().push((p.task_id.clone(), p.current, p.total));
+        })));
+
+        let result = with_task(task.clone(), || {
+            progress("step", 1, 2);
+            check_cancelled()?;
+            task.cancel();
+            progress("step", 2, 2);
+            check_cancelled()
+        });
+        assert_eq!(result, Err("Cancelled".to_string()));
+        assert_eq!(*seen.lock().unwrap(), vec![("t1".to_string(), 1, 2), ("t1".to_string(), 2, 2)]);
+
+        // Outside the task nothing is reported or cancelled
+        assert!(current().is_none());
+        assert!(check_cancelled().is_ok());
+
+        // A panicking task does not stay current
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            with_task(task.clone(), || panic!("boom"))
+        }));
+        assert!(panicked.is_err());
+        assert!(current().is_none());
+    }
+}
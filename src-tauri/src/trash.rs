@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::layout::ProjectLayout;
+use crate::metadata::SecretMetadata;
+
+const ENTRY_FILE: &str = "entry.json";
+/// Stored without the `.age` extension so project scans never pick it up.
+const CIPHERTEXT_FILE: &str = "ciphertext";
+
+/// A deleted secret: its ciphertext and the declaration it was removed from.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrashEntry {
+    pub id: String,
+    /// Original path relative to the secret root.
+    pub path: String,
+    /// The `publicKeys` expression the secret was declared with.
+    pub public_keys: String,
+    /// The exact statement(s) removed from the declarations file.
+    pub declaration: String,
+    /// Unix timestamp (seconds).
+    pub deleted_at: u64,
+    pub has_ciphertext: bool,
+    /// Metadata the secret had, restored along with it.
+    #[serde(default)]
+    pub metadata: Option<SecretMetadata>,
+}
+
+/// Trash lives under the project's `.thoughtseize` directory, outside the
+/// secret tree as far as scanning is concerned.
+pub fn trash_root(layout: &ProjectLayout) -> PathBuf {
+    layout.project_dir.join(".thoughtseize").join("trash")
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Build a new, unused trash id for a secret path.
+pub fn new_id(layout: &ProjectLayout, path: &str, deleted_at: u64) -> String {
+    let slug: String = path.trim_end_matches(".age")
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let root = trash_root(layout);
+    let base = format!("{}-{}", deleted_at, slug);
+    let mut id = base.clone();
+    let mut n = 1;
+    while root.join(&id).exists() {
+        n += 1;
+        id = format!("{}-{}", base, n);
+    }
+    id
+}
+
+pub fn entry_dir(layout: &ProjectLayout, id: &str) -> Result<PathBuf, String> {
+    if id.is_empty() || id.contains('/') || id.contains('\\') || id.starts_with('.') {
+        return Err(format!("Invalid trash id: {}", id));
+    }
+    Ok(trash_root(layout).join(id))
+}
+
+pub fn ciphertext_path(dir: &Path) -> PathBuf {
+    dir.join(CIPHERTEXT_FILE)
+}
+
+/// Move a secret's ciphertext (if any) into the trash and record its entry.
+pub fn put(layout: &ProjectLayout, entry: &TrashEntry, file: &Path) -> Result<PathBuf, String> {
+    let root = trash_root(layout);
+    let dir = root.join(&entry.id);
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create trash directory: {}", e))?;
+    // Keep trashed ciphertexts out of version control
+    let ignore = root.join(".gitignore");
+    if !ignore.exists() {
+        let _ = fs::write(&ignore, "*\n");
+    }
+
+    let json = serde_json::to_string_pretty(entry)
+        .map_err(|e| format!("Failed to serialize trash entry: {}", e))?;
+    fs::write(dir.join(ENTRY_FILE), json)
+        .map_err(|e| format!("Failed to write trash entry: {}", e))?;
+    if entry.has_ciphertext {
+        fs::rename(file, ciphertext_path(&dir))
+            .map_err(|e| format!("Failed to move {} to trash: {}", entry.path, e))?;
+    }
+    Ok(dir)
+}
+
+pub fn load(layout: &ProjectLayout, id: &str) -> Result<TrashEntry, String> {
+    let dir = entry_dir(layout, id)?;
+    let json = fs::read_to_string(dir.join(ENTRY_FILE))
+        .map_err(|_| format!("No trashed secret with id {}", id))?;
+    serde_json::from_str(&json)
+        .map_err(|e| format!("Invalid trash entry {}: {}", id, e))
+}
+
+/// All trashed secrets, most recently deleted first.
+pub fn list(layout: &ProjectLayout) -> Vec<TrashEntry> {
+    let Ok(dirs) = fs::read_dir(trash_root(layout)) else {
+        return Vec::new();
+    };
+    let mut entries: Vec<TrashEntry> = dirs
+        .filter_map(|d| d.ok())
+        .filter(|d| d.path().is_dir())
+        .filter_map(|d| load(layout, &d.file_name().to_string_lossy()).ok())
+        .collect();
+    entries.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then_with(|| a.id.cmp(&b.id)));
+    entries
+}
+
+pub fn remove(layout: &ProjectLayout, id: &str) -> Result<(), String> {
+    let dir = entry_dir(layout, id)?;
+    fs::remove_dir_all(&dir)
+        .map_err(|e| format!("Failed to remove trash entry {}: {}", id, e))
+}
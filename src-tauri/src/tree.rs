@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+
+/// A folder of secrets, for browsing large projects.
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct SecretTree {
+    pub name: String,
+    /// Relative to the secret root; empty for the root itself.
+    pub path: String,
+    /// Number of secrets in this folder and below.
+    pub count: usize,
+    pub folders: Vec<SecretTree>,
+    /// Secret paths directly in this folder.
+    pub secrets: Vec<String>,
+}
+
+#[derive(Default)]
+struct Node {
+    folders: BTreeMap<String, Node>,
+    secrets: Vec<String>,
+}
+
+impl Node {
+    fn into_tree(self, name: String, path: String) -> SecretTree {
+        let folders: Vec<SecretTree> = self.folders.into_iter()
+            .map(|(child, node)| {
+                let child_path = if path.is_empty() { child.clone() } else { format!("{}/{}", path, child) };
+                node.into_tree(child, child_path)
+            })
+            .collect();
+        let count = self.secrets.len() + folders.iter().map(|f| f.count).sum::<usize>();
+        SecretTree { name, path, count, folders, secrets: self.secrets }
+    }
+}
+
+/// Group secret paths into folders, sorted by name.
+pub fn build(paths: &[String]) -> SecretTree {
+    let mut root = Node::default();
+    let mut sorted: Vec<&String> = paths.iter().collect();
+    sorted.sort();
+    for path in sorted {
+        let mut node = &mut root;
+        let mut parts: Vec<&str> = path.split('/').collect();
+        parts.pop();
+        for part in parts {
+            node = node.folders.entry(part.to_string()).or_default();
+        }
+        node.secrets.push(path.clone());
+    }
+    root.into_tree(String::new(), String::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let paths: Vec<String> = ["watch/b.age", "top.age", "watch/a.age", "watch/deep/c.age"]
+            .iter().map(|s| s.to_string()).collect();
+        let tree = build(&paths);
+        assert_eq!(tree.count, 4);
+        assert_eq!(tree.secrets, vec!["top.age"]);
+        let watch = &tree.folders[0];
+        assert_eq!((watch.name.as_str(), watch.path.as_str(), watch.count), ("watch", "watch", 3));
+        assert_eq!(watch.secrets, vec!["watch/a.age", "watch/b.age"]);
+        assert_eq!(watch.folders[0].path, "watch/deep");
+        assert_eq!(watch.folders[0].count, 1);
+    }
+}
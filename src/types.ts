@@ -1,12 +1,75 @@
 export interface SecretFileInfo {
   path: string;
   groups: string[];
+  armor: boolean;
+  metadata: SecretMetadata | null;
+  status: SecretStatus;
+}
+
+export type GitStatus = "untracked" | "modified" | "staged";
+
+export interface SecretStatus {
+  /** Declared in the rules but there is no `.age` file. */
+  missing: boolean;
+  /** An `.age` file the rules do not declare. */
+  undeclared: boolean;
+  /** Null when the age header cannot tell, as for age identities. */
+  decryptable: boolean | null;
+  /** Null until `check_recipients` has evaluated the current rules. */
+  recipients_stale: boolean | null;
+  git: GitStatus | null;
+  modified: string | null;
+}
+
+export interface SecretMetadata {
+  description?: string;
+  owner?: string;
+  tags?: string[];
+  /** RFC 3339 timestamps. */
+  created?: string;
+  rotated?: string;
+  rotation_interval_days?: number;
+}
+
+export type LayoutKind = "meta_file" | "secrets_nix" | "custom";
+
+export interface ProjectLayout {
+  kind: LayoutKind;
+  project_dir: string;
+  rules_file: string;
+  declarations_file: string;
+  secret_root: string;
+}
+
+export interface LayoutOverrides {
+  rules_file?: string;
+  declarations_file?: string;
+  secret_root?: string;
+}
+
+export interface SecretTree {
+  name: string;
+  /** Relative to the secret root; empty for the root itself. */
+  path: string;
+  /** Number of secrets in this folder and below. */
+  count: number;
+  folders: SecretTree[];
+  /** Secret paths directly in this folder. */
+  secrets: string[];
 }
 
 export interface ProjectInfo {
   path: string;
+  /** The selected secret root. */
+  layout: ProjectLayout;
+  /** Every secret root in `path`; switch with `select_root`. */
+  roots: ProjectLayout[];
   secrets: SecretFileInfo[];
+  /** The same secrets grouped into folders, with counts. */
+  tree: SecretTree;
   groups: string[];
+  /** Why some status flags could not be computed. */
+  warnings: string[];
 }
 
 export interface IdentityInfo {
@@ -21,3 +84,240 @@ export interface TreeNode {
   isFile: boolean;
   groups?: string[];
 }
+
+export type SecretFormat = "dotenv" | "json" | "yaml" | "raw";
+
+export interface SecretField {
+  key: string;
+  value: string;
+}
+
+export interface StructuredSecret {
+  format: SecretFormat;
+  fields: SecretField[];
+}
+
+export interface PlanPreview {
+  id: string;
+  diff: string;
+  create: string[];
+  reencrypt: string[];
+  delete: string[];
+}
+
+export type ExportFormat = "dotenv" | "json" | "shell";
+
+export interface ExportNaming {
+  style: "basename" | "full_path";
+  prefix?: string;
+  uppercase?: boolean;
+}
+
+export interface ExportResult {
+  path: string;
+  count: number;
+  warnings: string[];
+}
+
+export interface TrashEntry {
+  id: string;
+  path: string;
+  public_keys: string;
+  declaration: string;
+  deleted_at: number;
+  has_ciphertext: boolean;
+  metadata: SecretMetadata | null;
+}
+
+export type ChangeSource = "metadata" | "git" | "created";
+
+export interface RotationStatus {
+  path: string;
+  last_changed: string;
+  source: ChangeSource;
+  interval_days: number;
+  due: string;
+  /** Negative when overdue. */
+  days_left: number;
+}
+
+export interface RotationReport {
+  overdue: RotationStatus[];
+  due_soon: RotationStatus[];
+  ok: RotationStatus[];
+  unscheduled: string[];
+  warnings: string[];
+}
+
+/** Payload of the `task-progress` event emitted by long-running commands. */
+export interface TaskProgress {
+  task_id: string;
+  message: string;
+  current: number;
+  total: number;
+}
+
+/** Time limits for external programs, in seconds; 0 disables a limit. */
+export interface Timeouts {
+  nix_secs: number;
+  age_secs: number;
+  git_secs: number;
+}
+
+export type MatrixFormat = "csv" | "markdown";
+
+export interface Principal {
+  /** The key without its comment. */
+  key: string;
+  label: string;
+  groups: string[];
+}
+
+export interface AccessRow {
+  path: string;
+  /** One entry per principal, in `AccessMatrix.principals` order. */
+  readers: boolean[];
+}
+
+export interface AccessMatrix {
+  principals: Principal[];
+  secrets: AccessRow[];
+}
+
+export interface LabelledKey {
+  key: string;
+  label: string;
+}
+
+export interface AccessChange {
+  path: string;
+  gained: LabelledKey[];
+  lost: LabelledKey[];
+}
+
+export interface GroupChangePreview {
+  plan: PlanPreview;
+  changes: AccessChange[];
+}
+
+export interface OffboardResult {
+  plan: PlanPreview;
+  changes: AccessChange[];
+  /** Secrets the key could decrypt before; rotate their values. */
+  rotate: string[];
+  removed_bindings: string[];
+  warnings: string[];
+}
+
+export interface OnboardResult {
+  plan: PlanPreview;
+  changes: AccessChange[];
+}
+
+export interface KeyInfo {
+  /** The key without its comment. */
+  key: string;
+  /** `age`, or the SSH key type such as `ssh-ed25519`. */
+  kind: string;
+  fingerprint: string | null;
+  comment: string | null;
+  label: string | null;
+  bindings: string[];
+  groups: string[];
+  /** Number of secrets this key can decrypt. */
+  secrets: number;
+  bits: number | null;
+}
+
+export interface KeyInventory {
+  keys: KeyInfo[];
+  warnings: string[];
+}
+
+export interface MetaEntry {
+  /** Attribute path such as `meta.ssh.groups.TECH`. */
+  path: string;
+  /** What to pass as a group: `path`, or `[ path ]` for a single key. */
+  expression: string;
+  keys: string[];
+}
+
+export interface GroupMember {
+  reference: string;
+  keys: LabelledKey[];
+}
+
+export interface ExpandedGroup {
+  name: string;
+  definition: string;
+  keys: LabelledKey[];
+  /** The bindings and `meta` paths the definition is built from. */
+  members: GroupMember[];
+}
+
+export interface GroupList {
+  groups: ExpandedGroup[];
+  /** Set when keys could not be resolved; groups then have no keys. */
+  warnings: string[];
+}
+
+export interface Consumer {
+  /** Relative to the repository root. */
+  file: string;
+  line: number;
+  /** The `age.secrets.<name>` attribute, if found. */
+  name: string | null;
+  /** False when matched by the end of an interpolated string. */
+  exact: boolean;
+}
+
+export interface SecretConsumers {
+  path: string;
+  consumers: Consumer[];
+}
+
+export interface ConsumerReport {
+  secrets: SecretConsumers[];
+  unused: string[];
+  warnings: string[];
+}
+
+export type HostProblemKind = "not_recipient" | "not_declared";
+
+export interface HostProblem {
+  host: string;
+  /** The `age.secrets.<name>` attribute. */
+  name: string;
+  file: string;
+  path: string | null;
+  kind: HostProblemKind;
+}
+
+export interface HostSummary {
+  /** The `nixosConfigurations` attribute. */
+  name: string;
+  host_name: string;
+  keys: LabelledKey[];
+  secrets: number;
+}
+
+export interface HostCheck {
+  hosts: HostSummary[];
+  problems: HostProblem[];
+  warnings: string[];
+}
+
+export interface AgeSecretOptions {
+  owner: string | null;
+  group: string | null;
+  mode: string | null;
+  /** May contain `{name}`. */
+  path: string | null;
+}
+
+export interface AgeSecretDeclaration {
+  name: string;
+  snippet: string;
+  /** The edit to the chosen module, when one was given. */
+  plan: PlanPreview | null;
+}